@@ -1,27 +1,26 @@
-use std::{net::SocketAddr, str, str::FromStr, time::Duration};
+use std::{str, time::Duration};
 
-use webrtc_proxy_client::TcpStream;
+use webrtc_proxy_client::{HttpEvent, HttpRequest};
 
 fn main() {
-    let mut socket = TcpStream::connect(
-        SocketAddr::from_str("34.238.34.226:80").unwrap(),
-        Some("http://127.0.0.1:14191"),
+    let mut request = HttpRequest::connect(
+        "GET",
+        "http://checkip.amazonaws.com/",
+        &[("User-Agent", "curl/7.79.1"), ("Accept", "*/*")],
+        None,
+        "http://127.0.0.1:14191",
     )
     .unwrap();
-    while !socket.connected(Duration::from_secs(3)).unwrap() {
+    while !request.connected(Duration::from_secs(3)).unwrap() {
         std::thread::sleep(Duration::from_millis(100));
     }
-    socket
-        .send(
-            "GET / HTTP/1.1\r\nHost: checkip.amazonaws.com\r\nUser-Agent: curl/7.79.1\r\nAccept: */*\r\n\r\n"
-                .as_bytes(),
-        )
-        .unwrap();
+    request.finish().unwrap();
     loop {
-        if let Some(packet) = socket.receive().unwrap() {
-            println!("{}", str::from_utf8(&packet).unwrap());
-            break;
+        match request.receive().unwrap() {
+            Some(HttpEvent::Head(head)) => println!("{} {}", head.status, head.status_text),
+            Some(HttpEvent::Body(body)) => print!("{}", str::from_utf8(&body).unwrap()),
+            Some(HttpEvent::End) => break,
+            None => std::thread::sleep(Duration::from_millis(10)),
         }
-        std::thread::sleep(Duration::from_millis(10));
     }
 }
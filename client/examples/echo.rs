@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use rusty_enet::Packet;
+use serde_json::json;
+use web_time::Instant;
+use webrtc_proxy_client::Proxied;
+
+fn main() {
+    let mut echo = Proxied::connect(
+        json!({ "Echo": { "latency_ms": 50, "jitter_ms": 10 } }),
+        "http://127.0.0.1:14191".to_owned(),
+    )
+    .unwrap();
+    while !echo.connected(Duration::from_secs(3)).unwrap() {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    for _ in 0..10 {
+        let sent = Instant::now();
+        echo.send(Packet::reliable(b"ping")).unwrap();
+        while echo.receive().unwrap().is_none() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        println!("round trip: {:?}", sent.elapsed());
+    }
+
+    let mut chargen = Proxied::connect(
+        json!({ "Chargen": { "rate": 1024 * 1024, "size": 1024 } }),
+        "http://127.0.0.1:14191".to_owned(),
+    )
+    .unwrap();
+    while !chargen.connected(Duration::from_secs(3)).unwrap() {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let start = Instant::now();
+    let mut received = 0;
+    while start.elapsed() < Duration::from_secs(5) {
+        match chargen.receive().unwrap() {
+            Some(data) => received += data.len(),
+            None => std::thread::sleep(Duration::from_millis(1)),
+        }
+    }
+    println!(
+        "throughput: {:.1} KiB/s",
+        received as f64 / 1024. / start.elapsed().as_secs_f64()
+    );
+}
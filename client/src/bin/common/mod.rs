@@ -0,0 +1,120 @@
+//! Shared by the command-line tools.
+
+// Not every tool uses everything.
+#![allow(dead_code)]
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{self, Shutdown},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+use webrtc_proxy_client::TcpStream;
+
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Bytes relayed in each direction, shared between the threads of a forward.
+#[derive(Default)]
+pub struct Traffic {
+    pub sent: AtomicU64,
+    pub received: AtomicU64,
+}
+
+impl Traffic {
+    pub fn add_sent(&self, bytes: usize) {
+        self.sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn add_received(&self, bytes: usize) {
+        self.received.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+/// Opens a TCP connection through `proxy` to `address`, as `host:port` for the proxy to resolve,
+/// and waits for it to reach the target.
+pub fn connect_tcp(address: &str, proxy: &str) -> Result<TcpStream> {
+    let mut stream = TcpStream::connect_host(address, proxy)?;
+    while !stream.connected(CONNECT_TIMEOUT)? {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    Ok(stream)
+}
+
+/// Copies data both ways between a local socket and a proxied stream, passing half-closes along,
+/// until both sides are done.
+pub fn relay_tcp(
+    mut local: net::TcpStream,
+    remote: &mut TcpStream,
+    traffic: &Traffic,
+) -> Result<()> {
+    local.set_nonblocking(true)?;
+    let mut local_eof = false;
+    let mut remote_eof = false;
+    // Data from the remote side the local socket hasn't taken yet.
+    let mut pending = Vec::new();
+    let mut buffer = [0; 1024];
+    while !(local_eof && remote_eof && pending.is_empty()) {
+        let mut idle = true;
+        if !local_eof {
+            match local.read(&mut buffer) {
+                Ok(0) => {
+                    local_eof = true;
+                    remote.shutdown(Shutdown::Write)?;
+                }
+                Ok(received) => {
+                    remote.send(&buffer[..received])?;
+                    traffic.add_sent(received);
+                    idle = false;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if !remote_eof && pending.is_empty() {
+            match remote.receive()? {
+                Some(data) if data.is_empty() => {
+                    remote_eof = true;
+                    local.shutdown(Shutdown::Write)?;
+                }
+                Some(data) => {
+                    traffic.add_received(data.len());
+                    pending = data;
+                    idle = false;
+                }
+                None => {}
+            }
+        }
+        if !pending.is_empty() {
+            match local.write(&pending) {
+                Ok(sent) => {
+                    pending.drain(..sent);
+                    idle = false;
+                }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if idle {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+    Ok(())
+}
+
+/// Bytes with a binary unit, like `1.5 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,242 @@
+//! Pipes stdin to a channel opened through the proxy, and what comes back to stdout, like netcat.
+//!
+//! Usage: `wrp-cat [options] <proxy-url> <channel>`, where the channel is `tcp:host:port`,
+//! `udp:host:port`, `echo`, `discard`, `chargen`, `sink` or a config in JSON, like
+//! `'{"Echo": {"latency_ms": 50}}'`.
+//!
+//! Options:
+//! - `--timeout <secs>`: how long to wait for the channel to connect, 10 by default.
+//! - `--wait <secs>`: after stdin closes, how long to wait for more data from channels that never
+//!   close their side, 2 by default.
+//! - `--hex`: write what comes back as a hex dump.
+//! - `-v`, `--verbose`: trace the connection and every packet on stderr.
+
+use std::{
+    io::{self, Read, Write},
+    sync::mpsc::{self, TryRecvError},
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use rusty_enet::Packet;
+use serde_json::{json, Value};
+use web_time::Instant;
+use webrtc_proxy_client::Proxied;
+
+const USAGE: &str = "usage: wrp-cat [--timeout secs] [--wait secs] [--hex] [-v] <proxy-url> \
+                     <tcp:host:port|udp:host:port|echo|discard|chargen|sink|json>";
+
+struct Options {
+    proxy: String,
+    config: Value,
+    timeout: Duration,
+    wait: Duration,
+    hex: bool,
+    verbose: bool,
+}
+
+impl Options {
+    fn parse() -> Result<Self> {
+        let mut args = std::env::args().skip(1);
+        let mut positional = vec![];
+        let mut timeout = Duration::from_secs(10);
+        let mut wait = Duration::from_secs(2);
+        let mut hex = false;
+        let mut verbose = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--timeout" => timeout = parse_secs(args.next())?,
+                "--wait" => wait = parse_secs(args.next())?,
+                "--hex" => hex = true,
+                "-v" | "--verbose" => verbose = true,
+                _ if arg.starts_with('-') => bail!("Unknown option {}.", arg),
+                _ => positional.push(arg),
+            }
+        }
+        let [proxy, channel] = <[String; 2]>::try_from(positional).map_err(|_| anyhow!(USAGE))?;
+        Ok(Self {
+            proxy,
+            config: channel_config(&channel)?,
+            timeout,
+            wait,
+            hex,
+            verbose,
+        })
+    }
+
+    fn trace(&self, message: impl AsRef<str>) {
+        if self.verbose {
+            eprintln!("* {}", message.as_ref());
+        }
+    }
+}
+
+fn parse_secs(secs: Option<String>) -> Result<Duration> {
+    let secs = secs.ok_or_else(|| anyhow!(USAGE))?;
+    Ok(Duration::from_secs_f64(secs.parse()?))
+}
+
+fn channel_config(channel: &str) -> Result<Value> {
+    if channel.starts_with('{') || channel.starts_with('"') {
+        return Ok(serde_json::from_str(channel)?);
+    }
+    Ok(match channel.split_once(':') {
+        Some(("tcp", address)) => json!({ "Tcp": address }),
+        Some(("udp", address)) => json!({ "Udp": address }),
+        _ => match channel {
+            "echo" => json!({ "Echo": {} }),
+            "discard" => json!("Discard"),
+            "chargen" => json!({ "Chargen": {} }),
+            "sink" => json!({ "Sink": {} }),
+            _ => bail!("Unknown channel {}.", channel),
+        },
+    })
+}
+
+/// Writes `data` as lines of 16 bytes: the offset, the bytes in hex, then as ASCII.
+fn hex_dump(output: &mut impl Write, offset: usize, data: &[u8]) -> io::Result<()> {
+    for (line, chunk) in data.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (index, byte) in chunk.iter().enumerate() {
+            if index == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+        let ascii = chunk
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        writeln!(
+            output,
+            "{:08x}  {:<49} |{}|",
+            offset + line * 16,
+            hex,
+            ascii
+        )?;
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match Options::parse() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+    if let Err(err) = run(&options) {
+        eprintln!("wrp-cat: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(options: &Options) -> Result<()> {
+    // UDP is the only channel where loss is expected, so only it goes unreliable.
+    let unreliable = options.config.get("Udp").is_some();
+    options.trace(format!("connecting to {}", options.proxy));
+    options.trace(format!("channel config {}", options.config));
+    let started = Instant::now();
+    let mut proxied = Proxied::connect(options.config.clone(), options.proxy.clone())?;
+    while !proxied.connected(options.timeout)? {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    options.trace(format!("connected in {:?}", started.elapsed()));
+
+    // Reading stdin blocks, so it happens on its own thread. An empty chunk means it closed.
+    let (sender, stdin) = mpsc::channel::<Vec<u8>>();
+    std::thread::spawn(move || {
+        let mut buffer = [0; 1024];
+        loop {
+            let received = io::stdin().read(&mut buffer).unwrap_or(0);
+            if sender.send(buffer[..received].to_vec()).is_err() || received == 0 {
+                break;
+            }
+        }
+    });
+
+    let mut stdout = io::stdout();
+    let mut stdin_closed_at = None;
+    let mut last_received = Instant::now();
+    let mut offset = 0;
+    let mut resuming = false;
+    loop {
+        let mut idle = true;
+        if stdin_closed_at.is_none() {
+            match stdin.try_recv() {
+                Ok(data) if !data.is_empty() => {
+                    options.trace(format!("> {} bytes", data.len()));
+                    proxied.send(if unreliable {
+                        Packet::unreliable_unsequenced(&data)
+                    } else {
+                        Packet::reliable(&data)
+                    })?;
+                    idle = false;
+                }
+                Ok(_) | Err(TryRecvError::Disconnected) => {
+                    options.trace("> shutdown");
+                    proxied.shutdown()?;
+                    stdin_closed_at = Some(Instant::now());
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+        while let Some(data) = proxied.receive()? {
+            options.trace(format!("< {} bytes", data.len()));
+            if options.hex {
+                hex_dump(&mut stdout, offset, &data)?;
+            } else {
+                stdout.write_all(&data)?;
+            }
+            stdout.flush()?;
+            offset += data.len();
+            last_received = Instant::now();
+            idle = false;
+        }
+        if proxied.resuming() != resuming {
+            resuming = proxied.resuming();
+            options.trace(if resuming {
+                "transport lost, resuming"
+            } else {
+                "resumed"
+            });
+        }
+        if proxied.eof() {
+            options.trace("< shutdown");
+            break;
+        }
+        if let Some(closed_at) = stdin_closed_at {
+            if closed_at.elapsed() > options.wait && last_received.elapsed() > options.wait {
+                break;
+            }
+        }
+        if idle {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    let stats = proxied.stats();
+    options.trace(format!(
+        "sent {} bytes in {} packets, received {} bytes in {} packets, rtt {:?}",
+        stats.bytes_sent,
+        stats.packets_sent,
+        stats.bytes_received,
+        stats.packets_received,
+        stats.round_trip_time,
+    ));
+    proxied.close()?;
+    let closing = Instant::now();
+    while !proxied.closed()? && closing.elapsed() < Duration::from_secs(1) {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    options.trace("closed");
+    Ok(())
+}
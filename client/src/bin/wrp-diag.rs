@@ -0,0 +1,413 @@
+//! Checks a proxy end to end and prints a JSON report, with a separate result for every stage so
+//! it's clear where things go wrong.
+//!
+//! Usage: `wrp-diag [options] <proxy-url>`.
+//!
+//! Options:
+//! - `--timeout <secs>`: how long each stage may take to connect, 10 by default.
+//! - `--ip-url <url>`: where to look up the egress IP, `http://checkip.amazonaws.com/` by default.
+//! - `--target <host:port>`: also try a TCP connection to this target.
+//!
+//! The stages are:
+//! - `signaling`: an HTTP request to the proxy's WebRTC session endpoint, or a WebSocket
+//!   handshake for WebSocket URLs. Any answer counts, since only the transport can tell a good one.
+//! - `transport`: the WebRTC session or WebSocket coming up.
+//! - `enet`: the ENet connection over that transport.
+//! - `echo`: round trips through an Echo channel.
+//! - `throughput`: download speed from a Chargen channel.
+//! - `egress_ip`: the address the proxy's traffic comes from.
+//! - `target`: the optional TCP connection.
+//!
+//! Stages that depend on one that failed are skipped. Exits with 1 if any stage failed.
+
+use std::{str, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use enaia_client::{EnaiaClient, EnaiaConnectionState, EnaiaEvent, EnaiaUrl};
+use rusty_enet::{crc32, Event, Host, HostSettings, Packet, RangeCoder};
+use serde::Serialize;
+use serde_json::{json, Value};
+use web_time::Instant;
+use webrtc_proxy_client::{HttpEvent, HttpRequest, Proxied, TcpStream, DISCONNECT_CLOSED};
+
+const ECHO_PINGS: usize = 10;
+/// naia's default session endpoint path, which [`EnaiaClient`] posts its offer to.
+const RTC_ENDPOINT_PATH: &str = "rtc_session";
+const THROUGHPUT_DURATION: Duration = Duration::from_secs(3);
+
+struct Options {
+    proxy: String,
+    timeout: Duration,
+    ip_url: String,
+    target: Option<String>,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+#[derive(Serialize)]
+struct Stage {
+    name: &'static str,
+    outcome: Outcome,
+    duration_ms: Option<u64>,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    details: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Report {
+    proxy: String,
+    ok: bool,
+    stages: Vec<Stage>,
+}
+
+impl Report {
+    /// Runs `check` unless a stage in `requires` didn't succeed, and records the result.
+    fn stage(
+        &mut self,
+        name: &'static str,
+        requires: &[&str],
+        check: impl FnOnce() -> Result<Value>,
+    ) {
+        let blocked = self
+            .stages
+            .iter()
+            .any(|stage| requires.contains(&stage.name) && stage.outcome != Outcome::Ok);
+        if blocked {
+            eprintln!("{}: skipped", name);
+            self.stages.push(Stage {
+                name,
+                outcome: Outcome::Skipped,
+                duration_ms: None,
+                details: Value::Null,
+                error: None,
+            });
+            return;
+        }
+        let started = Instant::now();
+        let result = check();
+        let duration_ms = started.elapsed().as_millis() as u64;
+        let stage = match result {
+            Ok(details) => {
+                eprintln!("{}: ok in {} ms", name, duration_ms);
+                Stage {
+                    name,
+                    outcome: Outcome::Ok,
+                    duration_ms: Some(duration_ms),
+                    details,
+                    error: None,
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: failed: {}", name, err);
+                self.ok = false;
+                Stage {
+                    name,
+                    outcome: Outcome::Failed,
+                    duration_ms: Some(duration_ms),
+                    details: Value::Null,
+                    error: Some(err.to_string()),
+                }
+            }
+        };
+        self.stages.push(stage);
+    }
+
+    fn details(&self, name: &str) -> Option<&Value> {
+        self.stages
+            .iter()
+            .find(|stage| stage.name == name)
+            .map(|stage| &stage.details)
+    }
+}
+
+fn parse_options() -> Result<Options> {
+    let mut args = std::env::args().skip(1);
+    let mut proxy = None;
+    let mut timeout = Duration::from_secs(10);
+    let mut ip_url = "http://checkip.amazonaws.com/".to_owned();
+    let mut target = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout" => {
+                let secs = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--timeout needs a value."))?;
+                timeout = Duration::from_secs_f64(secs.parse()?);
+            }
+            "--ip-url" => {
+                ip_url = args
+                    .next()
+                    .ok_or_else(|| anyhow!("--ip-url needs a value."))?;
+            }
+            "--target" => {
+                target = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow!("--target needs a value."))?,
+                );
+            }
+            _ if arg.starts_with('-') => bail!("Unknown option {}.", arg),
+            _ if proxy.is_none() => proxy = Some(arg),
+            _ => bail!("Unexpected argument {}.", arg),
+        }
+    }
+    Ok(Options {
+        proxy: proxy.ok_or_else(|| anyhow!("Missing proxy URL."))?,
+        timeout,
+        ip_url,
+        target,
+    })
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!(
+                "usage: wrp-diag [--timeout secs] [--ip-url url] [--target host:port] <proxy-url>"
+            );
+            std::process::exit(2);
+        }
+    };
+    let mut report = Report {
+        proxy: options.proxy.clone(),
+        ok: true,
+        stages: vec![],
+    };
+
+    report.stage("signaling", &[], || check_signaling(&options));
+    let mut enet = None;
+    report.stage("transport", &["signaling"], || {
+        let (details, enet_result) = check_transport(&options)?;
+        enet = Some(enet_result);
+        Ok(details)
+    });
+    report.stage("enet", &["transport"], || {
+        enet.unwrap_or_else(|| Err(anyhow!("Not attempted.")))
+    });
+    report.stage("echo", &["enet"], || check_echo(&options));
+    report.stage("throughput", &["enet"], || check_throughput(&options));
+    report.stage("egress_ip", &["enet"], || check_egress_ip(&options));
+    if let Some(target) = &options.target {
+        report.stage("target", &["enet"], || check_target(&options, target));
+    }
+    if let Some(Value::String(ip)) = report.details("egress_ip").and_then(|d| d.get("ip")) {
+        eprintln!("egress IP: {}", ip);
+    }
+
+    println!("{}", serde_json::to_string_pretty(&report).unwrap());
+    if !report.ok {
+        std::process::exit(1);
+    }
+}
+
+/// Sends what the proxy's signaling endpoint expects at first: a POST to the WebRTC session URL,
+/// or the start of a WebSocket handshake. The POST carries no offer and the handshake is never
+/// finished, so no session comes of either.
+fn check_signaling(options: &Options) -> Result<Value> {
+    let agent = ureq::AgentBuilder::new().timeout(options.timeout).build();
+    let (scheme, rest) = options
+        .proxy
+        .split_once("://")
+        .ok_or_else(|| anyhow!("{} is not a URL.", options.proxy))?;
+    let request = match scheme {
+        "http" | "https" => {
+            let url = format!(
+                "{}/{}",
+                options.proxy.trim_end_matches('/'),
+                RTC_ENDPOINT_PATH
+            );
+            agent.post(&url)
+        }
+        "ws" | "wss" => {
+            let url = format!(
+                "{}://{}",
+                if scheme == "ws" { "http" } else { "https" },
+                rest
+            );
+            agent
+                .get(&url)
+                .set("Connection", "Upgrade")
+                .set("Upgrade", "websocket")
+                .set("Sec-WebSocket-Version", "13")
+                .set("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+        }
+        _ => bail!("Unknown scheme {}.", scheme),
+    };
+    let url = request.url().to_owned();
+    let started = Instant::now();
+    let response = match request.call() {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(err) => return Err(err.into()),
+    };
+    Ok(json!({
+        "url": url,
+        "status": response.status(),
+        "response_ms": started.elapsed().as_millis() as u64,
+    }))
+}
+
+/// Connects a bare ENet host to the proxy, timing the transport and ENet separately. The ENet
+/// result is returned for its own stage.
+fn check_transport(options: &Options) -> Result<(Value, Result<Value>)> {
+    let client = EnaiaClient::new();
+    let status = client.status();
+    let mut host = Host::<EnaiaClient>::create(
+        client,
+        HostSettings {
+            peer_limit: 1,
+            channel_limit: 1,
+            compressor: Some(Box::new(RangeCoder::new())),
+            checksum: Some(Box::new(crc32)),
+            ..Default::default()
+        },
+    )?;
+    let url = EnaiaUrl::from(options.proxy.clone());
+    let started = Instant::now();
+    let peer = host.connect(url.clone(), 1, 0)?.id();
+    let mut transport_ms = None;
+    let mut errors = vec![];
+    loop {
+        while let Some(EnaiaEvent::Error { message, .. }) = status.poll_event() {
+            errors.push(message);
+        }
+        let event = host.service()?;
+        let state = status.state(&url);
+        if transport_ms.is_none() && state == Some(EnaiaConnectionState::Connected) {
+            transport_ms = Some(started.elapsed().as_millis() as u64);
+        }
+        if let Some(state) = state.filter(|state| state.is_failed()) {
+            bail!("{:?}{}", state, error_suffix(&errors));
+        }
+        match event {
+            Some(Event::Connect { .. }) => break,
+            Some(Event::Disconnect { .. }) => {
+                let details = json!({ "connect_ms": transport_ms });
+                return Ok((details, Err(anyhow!("Disconnected by the server."))));
+            }
+            _ => {}
+        }
+        if started.elapsed() > options.timeout {
+            if transport_ms.is_some() {
+                let details = json!({ "connect_ms": transport_ms });
+                return Ok((details, Err(anyhow!("Timed out."))));
+            }
+            bail!("Timed out{}", error_suffix(&errors));
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let enet_ms = started.elapsed().as_millis() as u64;
+    let transport_ms = transport_ms.unwrap_or(enet_ms);
+    if let Ok(peer) = host.peer_mut(peer) {
+        // Tells the server there's no session to keep for a reconnect.
+        peer.disconnect(DISCONNECT_CLOSED);
+    }
+    _ = host.flush();
+    Ok((
+        json!({ "connect_ms": transport_ms }),
+        Ok(json!({ "connect_ms": enet_ms - transport_ms })),
+    ))
+}
+
+fn error_suffix(errors: &[String]) -> String {
+    if errors.is_empty() {
+        ".".to_owned()
+    } else {
+        format!(": {}", errors.join("; "))
+    }
+}
+
+fn connect_channel(config: Value, options: &Options) -> Result<Proxied> {
+    let mut proxied = Proxied::connect(config, options.proxy.clone())?;
+    while !proxied.connected(options.timeout)? {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    Ok(proxied)
+}
+
+fn check_echo(options: &Options) -> Result<Value> {
+    let mut echo = connect_channel(json!({ "Echo": {} }), options)?;
+    let mut round_trips = vec![];
+    for _ in 0..ECHO_PINGS {
+        let sent = Instant::now();
+        echo.send(Packet::reliable(b"ping"))?;
+        while echo.receive()?.is_none() {
+            if sent.elapsed() > options.timeout {
+                bail!("No echo within {:?}.", options.timeout);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        round_trips.push(sent.elapsed().as_secs_f64() * 1000.);
+    }
+    _ = echo.close();
+    let min = round_trips.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = round_trips.iter().copied().fold(0., f64::max);
+    let average = round_trips.iter().sum::<f64>() / round_trips.len() as f64;
+    Ok(json!({ "pings": ECHO_PINGS, "min_ms": min, "average_ms": average, "max_ms": max }))
+}
+
+fn check_throughput(options: &Options) -> Result<Value> {
+    // The server caps this to its `chargen.max_rate`, 4 MiB/s by default.
+    let mut chargen = connect_channel(
+        json!({ "Chargen": { "rate": 4 * 1024 * 1024, "size": 1024 } }),
+        options,
+    )?;
+    let started = Instant::now();
+    let mut received = 0;
+    while started.elapsed() < THROUGHPUT_DURATION {
+        match chargen.receive()? {
+            Some(data) => received += data.len(),
+            None => std::thread::sleep(Duration::from_millis(1)),
+        }
+    }
+    _ = chargen.close();
+    let bytes_per_second = received as f64 / started.elapsed().as_secs_f64();
+    Ok(json!({ "bytes": received, "bytes_per_second": bytes_per_second }))
+}
+
+fn check_egress_ip(options: &Options) -> Result<Value> {
+    let mut request = HttpRequest::connect("GET", &options.ip_url, &[], None, &options.proxy)?;
+    while !request.connected(options.timeout)? {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    request.finish()?;
+    let started = Instant::now();
+    let mut status = None;
+    let mut body = vec![];
+    loop {
+        match request.receive()? {
+            Some(HttpEvent::Head(head)) => status = Some(head.status),
+            Some(HttpEvent::Body(data)) => body.extend(data),
+            Some(HttpEvent::End) => break,
+            None if started.elapsed() > options.timeout => bail!("No response."),
+            None => std::thread::sleep(Duration::from_millis(1)),
+        }
+    }
+    match status {
+        Some(status) if (200..300).contains(&status) => {
+            Ok(json!({ "ip": str::from_utf8(&body)?.trim(), "url": options.ip_url }))
+        }
+        Some(status) => bail!("{} answered {}.", options.ip_url, status),
+        None => bail!("No response."),
+    }
+}
+
+fn check_target(options: &Options, target: &str) -> Result<Value> {
+    let started = Instant::now();
+    let mut stream = TcpStream::connect_host(target, &options.proxy)?;
+    while !stream.connected(options.timeout)? {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    let connect_ms = started.elapsed().as_millis() as u64;
+    _ = stream.shutdown(std::net::Shutdown::Both);
+    Ok(json!({ "address": target, "connect_ms": connect_ms }))
+}
@@ -0,0 +1,247 @@
+//! Forwards local ports to remote targets through the proxy, like `ssh -L`.
+//!
+//! Usage: `wrp-forward <proxy-url> [--tcp local=remote]... [--udp local=remote]... [--stats secs]`,
+//! for example `--tcp 127.0.0.1:5432=db.internal:5432 --udp 127.0.0.1:7777=game:7777`. Remote
+//! hosts are resolved by the proxy, so names only it can resolve work. Traffic stats for every
+//! forward are printed every 10 seconds by default, or never with `--stats 0`.
+
+use std::{
+    collections::HashMap,
+    io::ErrorKind,
+    net::{self, SocketAddr, TcpListener},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Result};
+use common::{connect_tcp, format_bytes, relay_tcp, Traffic, CONNECT_TIMEOUT};
+use webrtc_proxy_client::UdpSocket;
+
+mod common;
+
+/// UDP flows without traffic either way for this long are dropped.
+const UDP_FLOW_TIMEOUT: Duration = Duration::from_secs(60);
+/// Datagrams held per flow while its proxied socket connects.
+const MAX_QUEUED_DATAGRAMS: usize = 64;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Protocol {
+    Tcp,
+    Udp,
+}
+
+struct Forward {
+    protocol: Protocol,
+    local: SocketAddr,
+    remote: String,
+    traffic: Traffic,
+    /// Connections or flows open now.
+    open: AtomicU64,
+    /// Connections or flows opened since starting.
+    total: AtomicU64,
+}
+
+impl Forward {
+    fn parse(protocol: Protocol, mapping: &str) -> Result<Self> {
+        let (local, remote) = mapping
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected local=remote, got {}.", mapping))?;
+        Ok(Self {
+            protocol,
+            local: local.parse()?,
+            remote: remote.to_owned(),
+            traffic: Traffic::default(),
+            open: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+        })
+    }
+
+    fn opened(&self) {
+        self.open.fetch_add(1, Ordering::Relaxed);
+        self.total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn closed(&self) {
+        self.open.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let usage = "usage: wrp-forward <proxy-url> [--tcp local=remote]... [--udp local=remote]... \
+                 [--stats secs]";
+    let Some(proxy) = args.next() else {
+        eprintln!("{}", usage);
+        std::process::exit(2);
+    };
+    let mut forwards = vec![];
+    let mut stats_interval = Duration::from_secs(10);
+    while let Some(arg) = args.next() {
+        let result = match (arg.as_str(), args.next()) {
+            ("--tcp", Some(mapping)) => Forward::parse(Protocol::Tcp, &mapping)
+                .map(|forward| forwards.push(Arc::new(forward))),
+            ("--udp", Some(mapping)) => Forward::parse(Protocol::Udp, &mapping)
+                .map(|forward| forwards.push(Arc::new(forward))),
+            ("--stats", Some(secs)) => secs
+                .parse()
+                .map(|secs| stats_interval = Duration::from_secs(secs))
+                .map_err(Into::into),
+            _ => Err(anyhow!("{}", usage)),
+        };
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    }
+    if forwards.is_empty() {
+        eprintln!("{}", usage);
+        std::process::exit(2);
+    }
+
+    for forward in &forwards {
+        let forward = forward.clone();
+        let proxy = proxy.clone();
+        match forward.protocol {
+            Protocol::Tcp => {
+                let listener = TcpListener::bind(forward.local).expect("could not listen");
+                std::thread::spawn(move || forward_tcp(listener, forward, proxy));
+            }
+            Protocol::Udp => {
+                let socket = net::UdpSocket::bind(forward.local).expect("could not bind");
+                std::thread::spawn(move || {
+                    if let Err(err) = forward_udp(socket, &forward, &proxy) {
+                        eprintln!("udp {}: {}", forward.local, err);
+                    }
+                });
+            }
+        }
+    }
+
+    loop {
+        if stats_interval.is_zero() {
+            std::thread::park();
+            continue;
+        }
+        std::thread::sleep(stats_interval);
+        for forward in &forwards {
+            println!(
+                "{} {} -> {}: {} open, {} total, {} sent, {} received",
+                match forward.protocol {
+                    Protocol::Tcp => "tcp",
+                    Protocol::Udp => "udp",
+                },
+                forward.local,
+                forward.remote,
+                forward.open.load(Ordering::Relaxed),
+                forward.total.load(Ordering::Relaxed),
+                format_bytes(forward.traffic.sent.load(Ordering::Relaxed)),
+                format_bytes(forward.traffic.received.load(Ordering::Relaxed)),
+            );
+        }
+    }
+}
+
+/// Each connection gets its own tunnel, so one lost to a proxy outage is closed and the next one
+/// connects afresh.
+fn forward_tcp(listener: TcpListener, forward: Arc<Forward>, proxy: String) {
+    for client in listener.incoming() {
+        let Ok(client) = client else {
+            continue;
+        };
+        let forward = forward.clone();
+        let proxy = proxy.clone();
+        std::thread::spawn(move || {
+            forward.opened();
+            let result = connect_tcp(&forward.remote, &proxy)
+                .and_then(|mut stream| relay_tcp(client, &mut stream, &forward.traffic));
+            forward.closed();
+            if let Err(err) = result {
+                eprintln!("tcp {} -> {}: {}", forward.local, forward.remote, err);
+            }
+        });
+    }
+}
+
+struct UdpFlow {
+    /// None after the proxied socket failed, until the next datagram reconnects it.
+    socket: Option<UdpSocket>,
+    queued: Vec<Vec<u8>>,
+    last_active: Instant,
+}
+
+fn forward_udp(local: net::UdpSocket, forward: &Forward, proxy: &str) -> Result<()> {
+    local.set_nonblocking(true)?;
+    let mut flows = HashMap::<SocketAddr, UdpFlow>::new();
+    let mut buffer = [0; 65536];
+    loop {
+        let mut idle = true;
+        loop {
+            let (received, from) = match local.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                // Windows reports ICMP port unreachable for earlier sends here.
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => continue,
+                Err(err) => return Err(err.into()),
+            };
+            idle = false;
+            let flow = flows.entry(from).or_insert_with(|| {
+                forward.opened();
+                UdpFlow {
+                    socket: None,
+                    queued: vec![],
+                    last_active: Instant::now(),
+                }
+            });
+            flow.last_active = Instant::now();
+            if flow.socket.is_none() {
+                match UdpSocket::connect_host(&forward.remote, proxy) {
+                    Ok(socket) => flow.socket = Some(socket),
+                    Err(err) => {
+                        eprintln!("udp {} -> {}: {}", forward.local, forward.remote, err);
+                        continue;
+                    }
+                }
+            }
+            if flow.queued.len() < MAX_QUEUED_DATAGRAMS {
+                flow.queued.push(buffer[..received].to_vec());
+            }
+        }
+        flows.retain(|from, flow| {
+            if let Some(socket) = &mut flow.socket {
+                let result = || -> Result<()> {
+                    if !socket.connected(CONNECT_TIMEOUT)? {
+                        return Ok(());
+                    }
+                    for datagram in flow.queued.drain(..) {
+                        socket.send(&datagram)?;
+                        forward.traffic.add_sent(datagram.len());
+                    }
+                    while let Some(data) = socket.receive()? {
+                        if local.send_to(&data, from).is_err() {
+                            bail!("Could not send to {}.", from);
+                        }
+                        forward.traffic.add_received(data.len());
+                        flow.last_active = Instant::now();
+                    }
+                    Ok(())
+                }();
+                if let Err(err) = result {
+                    eprintln!("udp {} -> {}: {}", forward.local, forward.remote, err);
+                    flow.socket = None;
+                    flow.queued.clear();
+                }
+            }
+            let keep = flow.last_active.elapsed() < UDP_FLOW_TIMEOUT;
+            if !keep {
+                forward.closed();
+            }
+            keep
+        });
+        if idle {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! A local SOCKS5 server that opens every connection through the proxy, so SOCKS-aware tools
+//! like curl can use the tunnel.
+//!
+//! Usage: `wrp-socks <proxy-url> [listen-address]`, listening on `127.0.0.1:1080` by default.
+//! Supports CONNECT and UDP ASSOCIATE without authentication.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{ErrorKind, Read, Write},
+    net::{self, IpAddr, Ipv4Addr, SocketAddr, TcpListener},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use common::{connect_tcp, relay_tcp, Traffic, CONNECT_TIMEOUT};
+use webrtc_proxy_client::{ProxyError, UdpSocket};
+
+mod common;
+
+const VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const NO_ACCEPTABLE_METHODS: u8 = 0xFF;
+
+const COMMAND_CONNECT: u8 = 1;
+const COMMAND_UDP_ASSOCIATE: u8 = 3;
+
+const ADDRESS_IPV4: u8 = 1;
+const ADDRESS_DOMAIN: u8 = 3;
+const ADDRESS_IPV6: u8 = 4;
+
+const REPLY_SUCCEEDED: u8 = 0;
+const REPLY_GENERAL_FAILURE: u8 = 1;
+const REPLY_HOST_UNREACHABLE: u8 = 4;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 7;
+const REPLY_ADDRESS_TYPE_NOT_SUPPORTED: u8 = 8;
+
+/// Datagrams held per destination while its proxied socket connects.
+const MAX_QUEUED_DATAGRAMS: usize = 64;
+/// UDP destinations without traffic either way for this long are dropped.
+const UDP_TARGET_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// A destination as the client gave it. Domains are left for the proxy to resolve, so names that
+/// only resolve on its side work and no lookup leaks from here.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Address {
+    Ip(SocketAddr),
+    Domain(String, u16),
+}
+
+/// `host:port`, as the proxy takes it.
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ip(address) => write!(f, "{}", address),
+            Self::Domain(domain, port) => write!(f, "{}:{}", domain, port),
+        }
+    }
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let Some(proxy) = args.next() else {
+        eprintln!("usage: wrp-socks <proxy-url> [listen-address]");
+        std::process::exit(2);
+    };
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:1080".to_owned());
+    let listener = TcpListener::bind(&address).expect("could not listen");
+    println!("SOCKS5 on {} through {}", address, proxy);
+    for client in listener.incoming() {
+        let Ok(client) = client else {
+            continue;
+        };
+        let proxy = proxy.clone();
+        std::thread::spawn(move || {
+            let peer = client.peer_addr();
+            if let Err(err) = handle(client, &proxy) {
+                if let Ok(peer) = peer {
+                    eprintln!("{}: {}", peer, err);
+                }
+            }
+        });
+    }
+}
+
+fn handle(mut client: net::TcpStream, proxy: &str) -> Result<()> {
+    let mut header = [0; 2];
+    client.read_exact(&mut header)?;
+    if header[0] != VERSION {
+        bail!("Not SOCKS5.");
+    }
+    let mut methods = vec![0; header[1] as usize];
+    client.read_exact(&mut methods)?;
+    if !methods.contains(&NO_AUTHENTICATION) {
+        client.write_all(&[VERSION, NO_ACCEPTABLE_METHODS])?;
+        bail!("Client requires authentication.");
+    }
+    client.write_all(&[VERSION, NO_AUTHENTICATION])?;
+
+    let mut request = [0; 3];
+    client.read_exact(&mut request)?;
+    if request[0] != VERSION {
+        bail!("Not SOCKS5.");
+    }
+    let Some(address) = read_address(&mut client)? else {
+        reply(&mut client, REPLY_ADDRESS_TYPE_NOT_SUPPORTED, None)?;
+        bail!("Unsupported address type.");
+    };
+    match request[1] {
+        COMMAND_CONNECT => connect(client, address, proxy),
+        COMMAND_UDP_ASSOCIATE => udp_associate(client, proxy),
+        command => {
+            reply(&mut client, REPLY_COMMAND_NOT_SUPPORTED, None)?;
+            bail!("Unsupported command {}.", command);
+        }
+    }
+}
+
+/// Reads an address type, address and port. None if the address type is unknown.
+fn read_address(reader: &mut impl Read) -> Result<Option<Address>> {
+    let mut kind = [0; 1];
+    reader.read_exact(&mut kind)?;
+    let ip = match kind[0] {
+        ADDRESS_IPV4 => {
+            let mut octets = [0; 4];
+            reader.read_exact(&mut octets)?;
+            Some(IpAddr::from(octets))
+        }
+        ADDRESS_IPV6 => {
+            let mut octets = [0; 16];
+            reader.read_exact(&mut octets)?;
+            Some(IpAddr::from(octets))
+        }
+        ADDRESS_DOMAIN => None,
+        _ => return Ok(None),
+    };
+    let domain = if ip.is_none() {
+        let mut length = [0; 1];
+        reader.read_exact(&mut length)?;
+        let mut domain = vec![0; length[0] as usize];
+        reader.read_exact(&mut domain)?;
+        Some(String::from_utf8(domain)?)
+    } else {
+        None
+    };
+    let mut port = [0; 2];
+    reader.read_exact(&mut port)?;
+    let port = u16::from_be_bytes(port);
+    match (ip, domain) {
+        (Some(ip), _) => Ok(Some(Address::Ip(SocketAddr::new(ip, port)))),
+        (None, Some(domain)) => Ok(Some(Address::Domain(domain, port))),
+        (None, None) => unreachable!(),
+    }
+}
+
+fn write_address(data: &mut Vec<u8>, address: &Address) {
+    let port = match address {
+        Address::Ip(address) => {
+            match address.ip() {
+                IpAddr::V4(ip) => {
+                    data.push(ADDRESS_IPV4);
+                    data.extend(ip.octets());
+                }
+                IpAddr::V6(ip) => {
+                    data.push(ADDRESS_IPV6);
+                    data.extend(ip.octets());
+                }
+            }
+            address.port()
+        }
+        Address::Domain(domain, port) => {
+            data.push(ADDRESS_DOMAIN);
+            data.push(domain.len() as u8);
+            data.extend(domain.as_bytes());
+            *port
+        }
+    };
+    data.extend(port.to_be_bytes());
+}
+
+fn reply(client: &mut net::TcpStream, code: u8, bound: Option<SocketAddr>) -> Result<()> {
+    let mut data = vec![VERSION, code, 0];
+    write_address(
+        &mut data,
+        &Address::Ip(bound.unwrap_or(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0))),
+    );
+    client.write_all(&data)?;
+    Ok(())
+}
+
+fn connect(mut client: net::TcpStream, address: Address, proxy: &str) -> Result<()> {
+    let mut stream = match connect_tcp(&address.to_string(), proxy) {
+        Ok(stream) => stream,
+        Err(err) => {
+            // The proxy itself failing isn't the target's fault.
+            let code = if err.downcast_ref::<ProxyError>().is_some() {
+                REPLY_GENERAL_FAILURE
+            } else {
+                REPLY_HOST_UNREACHABLE
+            };
+            reply(&mut client, code, None)?;
+            return Err(err);
+        }
+    };
+    reply(&mut client, REPLY_SUCCEEDED, None)?;
+    relay_tcp(client, &mut stream, &Traffic::default())
+}
+
+/// A destination the client sent datagrams to, with those still waiting for the proxied socket
+/// to connect.
+struct UdpTarget {
+    socket: UdpSocket,
+    queued: Vec<Vec<u8>>,
+    last_active: Instant,
+}
+
+fn udp_associate(mut client: net::TcpStream, proxy: &str) -> Result<()> {
+    let local = client.local_addr()?;
+    let relay = net::UdpSocket::bind(SocketAddr::new(local.ip(), 0))?;
+    relay.set_nonblocking(true)?;
+    reply(&mut client, REPLY_SUCCEEDED, Some(relay.local_addr()?))?;
+    client.set_nonblocking(true)?;
+
+    // The association ends when the client closes the control connection.
+    let mut targets = HashMap::<Address, UdpTarget>::new();
+    let client_ip = client.peer_addr()?.ip();
+    let mut client_address = None;
+    let mut buffer = [0; 65536];
+    loop {
+        match client.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err.into()),
+        }
+        let mut idle = true;
+        loop {
+            let (received, from) = match relay.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            };
+            idle = false;
+            if from.ip() != client_ip {
+                continue;
+            }
+            client_address = Some(from);
+            // Reserved bytes, then the fragment number. Fragments aren't supported.
+            let mut datagram = &buffer[..received];
+            if datagram.len() < 4 || datagram[2] != 0 {
+                continue;
+            }
+            datagram = &datagram[3..];
+            let Ok(Some(address)) = read_address(&mut datagram) else {
+                continue;
+            };
+            if !targets.contains_key(&address) {
+                let Ok(socket) = UdpSocket::connect_host(&address.to_string(), proxy) else {
+                    continue;
+                };
+                targets.insert(
+                    address.clone(),
+                    UdpTarget {
+                        socket,
+                        queued: vec![],
+                        last_active: Instant::now(),
+                    },
+                );
+            }
+            let target = targets.get_mut(&address).unwrap();
+            target.last_active = Instant::now();
+            if target.queued.len() < MAX_QUEUED_DATAGRAMS {
+                target.queued.push(datagram.to_vec());
+            }
+        }
+        let mut failed = vec![];
+        for (address, target) in &mut targets {
+            let result = || -> Result<()> {
+                if !target.socket.connected(CONNECT_TIMEOUT)? {
+                    return Ok(());
+                }
+                for datagram in target.queued.drain(..) {
+                    target.socket.send(&datagram)?;
+                }
+                while let Some(data) = target.socket.receive()? {
+                    let Some(client_address) = client_address else {
+                        continue;
+                    };
+                    let mut datagram = vec![0, 0, 0];
+                    write_address(&mut datagram, address);
+                    datagram.extend(data);
+                    relay.send_to(&datagram, client_address)?;
+                    target.last_active = Instant::now();
+                }
+                Ok(())
+            }();
+            if result.is_err() || target.last_active.elapsed() >= UDP_TARGET_TIMEOUT {
+                failed.push(address.clone());
+            }
+        }
+        for address in failed {
+            targets.remove(&address);
+        }
+        if idle {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
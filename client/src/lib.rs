@@ -1,15 +1,28 @@
 use std::{
     collections::VecDeque,
+    fmt,
     io::{ErrorKind, Read, Write},
     net::{self, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
     time::Duration,
 };
 
-use anyhow::{bail, Result};
-use enaia_client::EnaiaClient;
+use anyhow::{anyhow, bail, Result};
+use enaia_client::{EnaiaClient, EnaiaConnectionState, EnaiaStatus, EnaiaUrl};
 use rusty_enet::{crc32, Event, Host, HostSettings, Packet, PeerID, RangeCoder};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use web_time::Instant;
 
+mod mode;
+mod pool;
+mod session;
+
+pub use mode::*;
+pub use pool::*;
+pub use webrtc_proxy_shared::SocketOptions;
+
+use session::Session;
+
 fn unspecified_address(address: SocketAddr) -> SocketAddr {
     if address.is_ipv4() {
         SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))
@@ -18,21 +31,150 @@ fn unspecified_address(address: SocketAddr) -> SocketAddr {
     }
 }
 
+/// Failures of the WebRTC transport to the proxy itself, as opposed to the tunneled connection.
+/// Returned inside `anyhow::Error`, so callers can tell them apart with `downcast_ref`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyError {
+    /// The proxy's signaling endpoint couldn't be reached, or the WebRTC session never came up.
+    SignalingFailed,
+    /// The WebRTC session to the proxy came up, then was lost and couldn't be resumed in time.
+    TransportLost,
+}
+
+impl fmt::Display for ProxyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SignalingFailed => write!(f, "Signaling failed."),
+            Self::TransportLost => write!(f, "WebRTC transport lost."),
+        }
+    }
+}
+
+impl std::error::Error for ProxyError {}
+
+/// Set in the connect data to ask for a token to resume the tunnel with, which the server sends
+/// in a [`FRAME_SESSION`].
+const CONNECT_SESSION: u32 = 1 << 0;
+/// Set in the connect data when reconnecting to resume the tunnel. The first packet is then a
+/// [`FRAME_SESSION`] with the token and how many packets we received, which the server answers
+/// with how many it received, and both send again what the other is missing.
+const CONNECT_RESUME: u32 = 1 << 1;
+/// Set in the connect data to tell the server we frame what we send, see [`FRAME_DATA`]. Servers
+/// take packets from clients without it as bare data.
+const CONNECT_FRAMED: u32 = 1 << 2;
+/// Disconnect data telling the server the tunnel is no longer needed, so it doesn't keep the
+/// channel open for a reconnect.
+pub const DISCONNECT_CLOSED: u32 = 1;
+/// Disconnect data of a server that no longer has the session we tried to resume.
+const DISCONNECT_UNKNOWN_SESSION: u32 = 2;
+
+/// First byte of every packet on a channel after the config that opens it, in both directions. A
+/// bare close frame from the client asks the server to close the channel, and the server answers
+/// with one once it has.
+const FRAME_CLOSE: u8 = 0;
+const FRAME_DATA: u8 = 1;
+/// Nothing more will be sent in this direction. We send it to shut down writing to the target, and
+/// the server when the target has nothing more to send.
+const FRAME_SHUTDOWN: u8 = 2;
+/// Asks the server for its numbers on the channel, which it sends back as JSON after the same
+/// byte.
+const FRAME_STATS: u8 = 3;
+/// Sets up resumption, outside the channel's data. See [`CONNECT_SESSION`] and [`CONNECT_RESUME`].
+const FRAME_SESSION: u8 = 4;
+/// How many reliable packets were received so far, as a big-endian u32, so the other side can stop
+/// keeping them for a resume. Sent unreliably, in both directions.
+const FRAME_ACK: u8 = 5;
+
+/// How long to wait between attempts to reconnect, so an unreachable proxy isn't hammered.
+const RESUME_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to keep reconnecting after the transport is lost, matching the server's default grace
+/// period.
+pub const DEFAULT_RESUME_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ProxyStats {
+    pub round_trip_time: Duration,
+    pub round_trip_time_variance: Duration,
+    /// Fraction of reliable packets that had to be resent, from 0 to 1.
+    pub packet_loss: f32,
+    /// Data through the channel, not counting ENet's and the tunnel's own overhead.
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    /// Time since the channel connected to its target.
+    pub connected_for: Duration,
+}
+
+/// [`ProxyStats`] as the server sends them.
+#[derive(Deserialize)]
+struct ServerStats {
+    round_trip_time_ms: u64,
+    round_trip_time_variance_ms: u64,
+    packet_loss: f32,
+    bytes_sent: u64,
+    bytes_received: u64,
+    packets_sent: u64,
+    packets_received: u64,
+    connected_ms: u64,
+}
+
+impl From<ServerStats> for ProxyStats {
+    fn from(stats: ServerStats) -> Self {
+        Self {
+            round_trip_time: Duration::from_millis(stats.round_trip_time_ms),
+            round_trip_time_variance: Duration::from_millis(stats.round_trip_time_variance_ms),
+            packet_loss: stats.packet_loss,
+            bytes_sent: stats.bytes_sent,
+            bytes_received: stats.bytes_received,
+            packets_sent: stats.packets_sent,
+            packets_received: stats.packets_received,
+            connected_for: Duration::from_millis(stats.connected_ms),
+        }
+    }
+}
+
 pub struct Proxied {
-    address: SocketAddr,
-    protocol: &'static str,
+    config: Value,
+    proxy: EnaiaUrl,
+    status: EnaiaStatus,
     host: Host<EnaiaClient>,
     peer: PeerID,
+    session: Session,
     connect_time: Instant,
+    /// The ENet connection to the proxy is up, so the config can be sent.
+    tunnel_connected: bool,
+    /// The config was sent, and the server has, or is opening, the channel.
+    opened: bool,
     connected: bool,
+    closing: bool,
+    closed: bool,
+    shut_down: bool,
+    eof: bool,
     disconnected: bool,
     packets: VecDeque<Vec<u8>>,
+    resume_timeout: Duration,
+    /// When the transport was lost, while reconnecting to resume the session.
+    resuming: Option<Instant>,
+    reconnect_at: Option<Instant>,
+    connected_at: Option<Instant>,
+    bytes_sent: u64,
+    bytes_received: u64,
+    packets_sent: u64,
+    packets_received: u64,
+    server_stats: Option<ProxyStats>,
+    pool: Option<ProxyPool>,
+    /// Proxies to try next if this one can't be reached.
+    fallbacks: VecDeque<String>,
 }
 
 impl Proxied {
-    pub fn connect(address: SocketAddr, proxy: String, protocol: &'static str) -> Result<Self> {
+    pub fn connect(config: Value, proxy: String) -> Result<Self> {
+        let client = EnaiaClient::new();
+        let status = client.status();
         let mut host = Host::<EnaiaClient>::create(
-            EnaiaClient::new(),
+            client,
             HostSettings {
                 peer_limit: 1,
                 channel_limit: 1,
@@ -41,24 +183,85 @@ impl Proxied {
                 ..Default::default()
             },
         )?;
-        let peer = host.connect(proxy.into(), 1, 0)?.id();
+        let proxy = EnaiaUrl::from(proxy);
+        let peer = host
+            .connect(proxy.clone(), 1, CONNECT_FRAMED | CONNECT_SESSION)?
+            .id();
         Ok(Self {
-            address,
-            protocol,
+            config,
+            proxy,
+            status,
             host,
             peer,
+            session: Session::new(),
             connect_time: Instant::now(),
+            tunnel_connected: false,
+            opened: false,
             connected: false,
+            closing: false,
+            closed: false,
+            shut_down: false,
+            eof: false,
             disconnected: false,
             packets: VecDeque::new(),
+            resume_timeout: DEFAULT_RESUME_TIMEOUT,
+            resuming: None,
+            reconnect_at: None,
+            connected_at: None,
+            bytes_sent: 0,
+            bytes_received: 0,
+            packets_sent: 0,
+            packets_received: 0,
+            server_stats: None,
+            pool: None,
+            fallbacks: VecDeque::new(),
         })
     }
 
+    /// Connects through the first proxy of `pool` that can be reached, in the order of its
+    /// strategy. Once connected, it stays on that proxy.
+    pub fn connect_pool(config: Value, pool: &ProxyPool) -> Result<Self> {
+        let mut candidates = VecDeque::from(pool.candidates());
+        let Some(proxy) = candidates.pop_front() else {
+            bail!("No proxies.");
+        };
+        let mut proxied = Self::connect(config, proxy)?;
+        proxied.pool = Some(pool.clone());
+        proxied.fallbacks = candidates;
+        Ok(proxied)
+    }
+
+    /// The URL of the proxy in use, or being tried.
+    pub fn proxy(&self) -> &str {
+        &self.proxy.0
+    }
+
+    /// Sets how long to keep reconnecting to the proxy when the transport is lost after the
+    /// tunnel was established. The server keeps the tunnel open for its own grace period, so
+    /// longer than that doesn't help. Zero gives up right away.
+    pub fn set_resume_timeout(&mut self, timeout: Duration) {
+        self.resume_timeout = timeout;
+    }
+
+    /// True while reconnecting after the transport was lost. Reliable sends are kept until the
+    /// session is back, and unreliable ones dropped.
+    pub fn resuming(&self) -> bool {
+        self.resuming.is_some()
+    }
+
     pub fn connected(&mut self, timeout: Duration) -> Result<bool> {
         self.service()?;
         if !self.connected && self.connect_time.elapsed() > timeout {
+            if self.fail_over()? {
+                return Ok(false);
+            }
             self.disconnect();
-            bail!("Connection timeout.");
+            match self.status.state(&self.proxy) {
+                Some(EnaiaConnectionState::Connecting) | None => {
+                    Err(ProxyError::SignalingFailed.into())
+                }
+                _ => Err(self.disconnected_error()),
+            }
         } else {
             Ok(self.connected)
         }
@@ -66,70 +269,272 @@ impl Proxied {
 
     pub fn send(&mut self, packet: Packet) -> Result<()> {
         self.service()?;
-        if self.connected {
-            if let Err(_) = self
-                .host
-                .peer_mut(self.peer)
-                .and_then(|peer| peer.send(0, packet))
-            {
-                self.disconnect();
-                bail!("Socket not connected.");
-            }
+        if !self.connected {
+            bail!("Socket not connected.");
+        }
+        if self.shut_down {
+            bail!("Write side shut down.");
+        }
+        let mut data = vec![FRAME_DATA];
+        data.extend(packet.data());
+        self.send_packet(Packet::new(&data, packet.kind()))?;
+        self.bytes_sent += packet.data().len() as u64;
+        self.packets_sent += 1;
+        Ok(())
+    }
+
+    /// Numbers for this channel, with the round-trip time and packet loss of the connection to
+    /// the proxy.
+    pub fn stats(&self) -> ProxyStats {
+        let mut stats = ProxyStats {
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            packets_sent: self.packets_sent,
+            packets_received: self.packets_received,
+            connected_for: self
+                .connected_at
+                .map_or(Duration::ZERO, |connected_at| connected_at.elapsed()),
+            ..Default::default()
+        };
+        if let Ok(peer) = self.host.peer(self.peer) {
+            stats.round_trip_time = peer.round_trip_time();
+            stats.round_trip_time_variance = peer.round_trip_time_variance();
+            stats.packet_loss = peer.packet_loss();
+        }
+        stats
+    }
+
+    /// Asks the server for its numbers on this channel, which [`server_stats`](Self::server_stats)
+    /// returns once they arrive.
+    pub fn request_server_stats(&mut self) -> Result<()> {
+        self.service()?;
+        if !self.connected {
+            bail!("Socket not connected.");
+        }
+        self.send_packet(Packet::reliable(&[FRAME_STATS]))
+    }
+
+    /// The server's numbers from the latest [`request_server_stats`](Self::request_server_stats),
+    /// where bytes sent went to us.
+    pub fn server_stats(&self) -> Option<ProxyStats> {
+        self.server_stats
+    }
+
+    /// Tells the target nothing more will be sent, e.g. with a FIN for TCP, while still receiving
+    /// from it.
+    pub fn shutdown(&mut self) -> Result<()> {
+        self.service()?;
+        if !self.connected {
+            bail!("Socket not connected.");
+        }
+        if self.shut_down {
+            return Ok(());
+        }
+        self.shut_down = true;
+        self.send_packet(Packet::reliable(&[FRAME_SHUTDOWN]))
+    }
+
+    /// True once the target has nothing more to send. Packets received before that may still be
+    /// waiting in [`receive`](Self::receive).
+    pub fn eof(&self) -> bool {
+        self.eof
+    }
+
+    /// Closes the channel and the server's connection to its target, but keeps the connection to
+    /// the proxy. Once [`closed`](Self::closed) returns true, [`reopen`](Self::reopen) can open
+    /// another channel in its place.
+    pub fn close(&mut self) -> Result<()> {
+        self.service()?;
+        if self.closing || self.closed {
+            return Ok(());
+        }
+        self.connected = false;
+        self.packets.clear();
+        if self.opened {
+            self.closing = true;
+            self.send_packet(Packet::reliable(&[FRAME_CLOSE]))
         } else {
+            self.closed = true;
+            Ok(())
+        }
+    }
+
+    /// True once the server confirmed the channel is closed.
+    pub fn closed(&mut self) -> Result<bool> {
+        self.service()?;
+        Ok(self.closed)
+    }
+
+    /// Opens a new channel with `config` after the previous one was closed.
+    pub fn reopen(&mut self, config: Value) -> Result<()> {
+        self.service()?;
+        if !self.closed {
+            bail!("Channel not closed.");
+        }
+        self.config = config;
+        self.closed = false;
+        self.shut_down = false;
+        self.eof = false;
+        self.connected_at = None;
+        self.bytes_sent = 0;
+        self.bytes_received = 0;
+        self.packets_sent = 0;
+        self.packets_received = 0;
+        self.server_stats = None;
+        self.connect_time = Instant::now();
+        if self.tunnel_connected {
+            self.open()?;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self) -> Result<()> {
+        self.opened = true;
+        self.send_packet(Packet::reliable(self.config.to_string().as_bytes()))
+    }
+
+    fn send_packet(&mut self, packet: Packet) -> Result<()> {
+        self.session.sent(&packet);
+        if self.resuming.is_some() {
+            // Sent with whatever else the server is missing once the session is back.
+        } else if self
+            .host
+            .peer_mut(self.peer)
+            .and_then(|peer| peer.send(0, packet))
+            .is_err()
+        {
+            self.disconnect();
             bail!("Socket not connected.");
         }
         Ok(())
     }
 
     pub fn receive(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(packet) = self.packets.pop_front() {
+            return Ok(Some(packet));
+        }
         self.service()?;
         Ok(self.packets.pop_front())
     }
 
     fn service(&mut self) -> Result<()> {
         if self.disconnected {
-            bail!("Disconnected.");
+            return Err(self.disconnected_error());
+        }
+        if self
+            .resuming
+            .is_some_and(|since| since.elapsed() > self.resume_timeout)
+        {
+            self.resuming = None;
+            self.disconnect();
+            return Err(ProxyError::TransportLost.into());
+        }
+        if let Some(reconnect_at) = self.reconnect_at {
+            if Instant::now() < reconnect_at {
+                return Ok(());
+            }
+            self.reconnect()?;
+        }
+        if self
+            .status
+            .state(&self.proxy)
+            .is_some_and(|state| state.is_failed())
+        {
+            return self.transport_lost();
+        }
+        if self.tunnel_connected && self.resuming.is_none() {
+            if let Some(ack) = self.session.ack() {
+                self.send_now(vec![ack])?;
+            }
         }
         match self.host.service() {
+            Ok(Some(Event::Connect { .. })) if self.resuming.is_some() => {
+                let request = self.session.resume_request();
+                self.send_now(request.into_iter().collect())
+            }
             Ok(Some(Event::Connect { .. })) => {
-                if let Err(_) = self.host.peer_mut(self.peer).and_then(|peer| {
-                    peer.send(
-                        0,
-                        Packet::reliable(
-                            format!("{{\"{}\":\"{}\"}}", self.protocol, self.address).as_bytes(),
-                        ),
-                    )
-                }) {
-                    self.disconnect();
-                    bail!("Disconnected.");
+                self.tunnel_connected = true;
+                if !self.closed && self.open().is_err() {
+                    return Err(self.disconnected_error());
                 }
                 Ok(())
             }
-            Ok(Some(Event::Disconnect { .. })) => {
+            Ok(Some(Event::Disconnect { data, .. })) if data == DISCONNECT_UNKNOWN_SESSION => {
+                self.resuming = None;
                 self.disconnect();
-                bail!("Disconnected.");
+                bail!("Session expired.");
             }
+            Ok(Some(Event::Disconnect { .. })) => self.transport_lost(),
             Ok(Some(Event::Receive {
                 peer: _,
                 channel_id,
                 packet,
             })) => {
                 if channel_id == 0 {
+                    self.session.received(&packet);
+                    match packet.data().split_first() {
+                        Some((&FRAME_ACK, received)) => {
+                            if let Ok(received) = <[u8; 4]>::try_from(received) {
+                                self.session.acknowledge(u32::from_be_bytes(received));
+                            }
+                            return Ok(());
+                        }
+                        Some((&FRAME_SESSION, counts)) if self.resuming.is_some() => {
+                            let Some(missing) = self.session.resume(counts) else {
+                                self.resuming = None;
+                                self.disconnect();
+                                bail!("Session expired.");
+                            };
+                            self.resuming = None;
+                            return self.send_now(missing);
+                        }
+                        Some((&FRAME_SESSION, token)) => {
+                            self.session.set_token(token);
+                            return Ok(());
+                        }
+                        _ => {}
+                    }
                     if let Some(first_byte) = packet.data().first() {
-                        if *first_byte == 1 {
+                        if *first_byte == FRAME_CLOSE && self.closing {
+                            self.closing = false;
+                            self.closed = true;
+                            self.opened = false;
+                            Ok(())
+                        } else if self.closing || self.closed {
+                            // Sent before the server saw the close.
+                            Ok(())
+                        } else if *first_byte == FRAME_STATS {
+                            if let Ok(stats) =
+                                serde_json::from_slice::<ServerStats>(&packet.data()[1..])
+                            {
+                                self.server_stats = Some(stats.into());
+                            }
+                            Ok(())
+                        } else if *first_byte == FRAME_SHUTDOWN {
+                            self.eof = true;
+                            Ok(())
+                        } else if *first_byte == FRAME_DATA {
                             if !self.connected && packet.data().len() == 1 {
                                 self.connected = true;
+                                self.connected_at = Some(Instant::now());
+                                if let (Some(pool), Ok(peer)) =
+                                    (&self.pool, self.host.peer(self.peer))
+                                {
+                                    pool.report_success(&self.proxy.0, peer.round_trip_time());
+                                }
                             } else {
+                                self.bytes_received += packet.data().len() as u64 - 1;
+                                self.packets_received += 1;
                                 self.packets.push_back(packet.data()[1..].to_vec());
                             }
                             Ok(())
                         } else {
                             self.disconnect();
-                            bail!("Disconnected.");
+                            Err(self.disconnected_error())
                         }
                     } else {
                         self.disconnect();
-                        bail!("Disconnected.");
+                        Err(self.disconnected_error())
                     }
                 } else {
                     Ok(())
@@ -138,16 +543,100 @@ impl Proxied {
             Ok(None) => Ok(()),
             Err(_) => {
                 self.disconnect();
-                bail!("Disconnected.");
+                Err(self.disconnected_error())
+            }
+        }
+    }
+
+    /// Reconnects to resume the session if the tunnel was established and resumption is on, and
+    /// fails otherwise.
+    fn transport_lost(&mut self) -> Result<()> {
+        if !self.connected && self.fail_over()? {
+            return Ok(());
+        }
+        if !self.connected || self.resume_timeout.is_zero() || !self.session.resumable() {
+            self.disconnect();
+            return Err(self.disconnected_error());
+        }
+        if let Ok(peer) = self.host.peer_mut(self.peer) {
+            peer.reset();
+        }
+        self.reconnect_at = Some(match self.resuming {
+            Some(_) => Instant::now() + RESUME_RETRY_INTERVAL,
+            None => Instant::now(),
+        });
+        self.resuming.get_or_insert_with(Instant::now);
+        Ok(())
+    }
+
+    /// Moves on to the next proxy of the pool while the channel isn't up yet. Returns false when
+    /// there's none left.
+    fn fail_over(&mut self) -> Result<bool> {
+        if let Some(pool) = &self.pool {
+            pool.report_failure(&self.proxy.0);
+        }
+        let Some(proxy) = self.fallbacks.pop_front() else {
+            return Ok(false);
+        };
+        if let Ok(peer) = self.host.peer_mut(self.peer) {
+            peer.reset();
+        }
+        self.proxy = EnaiaUrl::from(proxy);
+        self.connect_time = Instant::now();
+        self.tunnel_connected = false;
+        self.opened = false;
+        self.session = Session::new();
+        self.peer = self
+            .host
+            .connect(self.proxy.clone(), 1, CONNECT_FRAMED | CONNECT_SESSION)?
+            .id();
+        Ok(true)
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.reconnect_at = None;
+        match self.host.connect(self.proxy.clone(), 1, CONNECT_RESUME) {
+            Ok(peer) => self.peer = peer.id(),
+            Err(_) => {
+                self.disconnect();
+                return Err(self.disconnected_error());
+            }
+        }
+        // Sends the connect request now, which replaces the failed transport before the next
+        // state check.
+        _ = self.host.flush();
+        Ok(())
+    }
+
+    /// Sends `packets` to the server right away, bypassing the session.
+    fn send_now(&mut self, packets: Vec<Packet>) -> Result<()> {
+        for packet in packets {
+            if self
+                .host
+                .peer_mut(self.peer)
+                .and_then(|peer| peer.send(0, packet))
+                .is_err()
+            {
+                self.disconnect();
+                return Err(self.disconnected_error());
             }
         }
+        Ok(())
+    }
+
+    fn disconnected_error(&self) -> anyhow::Error {
+        match self.status.state(&self.proxy) {
+            Some(EnaiaConnectionState::SignalingFailed) => ProxyError::SignalingFailed.into(),
+            Some(EnaiaConnectionState::TransportLost) => ProxyError::TransportLost.into(),
+            _ => anyhow!("Disconnected."),
+        }
     }
 
     fn disconnect(&mut self) {
         self.connected = false;
         self.disconnected = true;
         if let Ok(peer) = self.host.peer_mut(self.peer) {
-            _ = peer.disconnect(0);
+            _ = peer.disconnect(DISCONNECT_CLOSED);
         }
     }
 }
@@ -161,9 +650,8 @@ impl TcpStream {
     pub fn connect(address: SocketAddr, proxy: Option<&str>) -> Result<Self> {
         if let Some(proxy) = proxy {
             Ok(Self::Proxied(Proxied::connect(
-                address,
+                json!({ "Tcp": address }),
                 proxy.to_owned(),
-                "Tcp",
             )?))
         } else {
             let stream = net::TcpStream::connect(address)?;
@@ -172,6 +660,79 @@ impl TcpStream {
         }
     }
 
+    /// Connects directly or through `proxy` as `mode` says. With [`ConnectMode::Auto`], a failed
+    /// or slow direct connection falls back to the proxy.
+    pub fn connect_with_mode(address: SocketAddr, proxy: &str, mode: ConnectMode) -> Result<Self> {
+        if mode.try_direct() {
+            let stream = match mode {
+                ConnectMode::Auto { direct_timeout } => {
+                    net::TcpStream::connect_timeout(&address, direct_timeout)
+                }
+                _ => net::TcpStream::connect(address),
+            };
+            match stream {
+                Ok(stream) => {
+                    stream.set_nonblocking(true)?;
+                    return Ok(Self::Direct(Some(stream)));
+                }
+                Err(err) if mode == ConnectMode::Direct => return Err(err.into()),
+                Err(_) => {}
+            }
+        }
+        Self::connect(address, Some(proxy))
+    }
+
+    /// Like [`connect_with_mode`](Self::connect_with_mode), with the mode from the
+    /// `WEBRTC_PROXY_MODE` environment variable.
+    pub fn connect_auto(address: SocketAddr, proxy: &str) -> Result<Self> {
+        Self::connect_with_mode(address, proxy, ConnectMode::from_env()?)
+    }
+
+    /// Connects through `proxy`, with `options` for the proxy's socket to the target.
+    pub fn connect_with_options(
+        address: SocketAddr,
+        options: &SocketOptions,
+        proxy: &str,
+    ) -> Result<Self> {
+        Ok(Self::Proxied(Proxied::connect(
+            json!({ "Tcp": { "address": address, "options": options } }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    /// Connects through `proxy` to `address` given as `host:port`. The proxy resolves the host,
+    /// so names only it can resolve work, and nothing is looked up locally.
+    pub fn connect_host(address: &str, proxy: &str) -> Result<Self> {
+        Ok(Self::Proxied(Proxied::connect(
+            json!({ "Tcp": address }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    /// Connects to a backend of a service configured on the proxy, which picks the backend.
+    pub fn connect_service(name: &str, proxy: &str) -> Result<Self> {
+        Ok(Self::Proxied(Proxied::connect(
+            json!({ "Service": name }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    /// Connects through the first proxy of `pool` that can be reached.
+    pub fn connect_pool(address: SocketAddr, pool: &ProxyPool) -> Result<Self> {
+        Ok(Self::Proxied(Proxied::connect_pool(
+            json!({ "Tcp": address }),
+            pool,
+        )?))
+    }
+
+    /// The URL of the proxy in use, or None for direct connections.
+    pub fn proxy(&self) -> Option<&str> {
+        match self {
+            Self::Direct(_) => None,
+            Self::Proxied(proxied) => Some(proxied.proxy()),
+        }
+    }
+
     pub fn connected(&mut self, timeout: Duration) -> Result<bool> {
         match self {
             Self::Direct(stream) => {
@@ -208,6 +769,26 @@ impl TcpStream {
         }
     }
 
+    /// Only the write side can be shut down through a proxy, so `Read` does nothing there and
+    /// `Both` acts like `Write`.
+    pub fn shutdown(&mut self, how: net::Shutdown) -> Result<()> {
+        match self {
+            Self::Direct(stream) => {
+                if let Some(stream) = stream {
+                    stream.shutdown(how)?;
+                    Ok(())
+                } else {
+                    bail!("Disconnected.");
+                }
+            }
+            Self::Proxied(proxied) => match how {
+                net::Shutdown::Read => Ok(()),
+                net::Shutdown::Write | net::Shutdown::Both => proxied.shutdown(),
+            },
+        }
+    }
+
+    /// Returns an empty packet once the other side has nothing more to send.
     pub fn receive(&mut self) -> Result<Option<Vec<u8>>> {
         match self {
             Self::Direct(stream) => {
@@ -229,7 +810,18 @@ impl TcpStream {
                     bail!("Disconnected.");
                 }
             }
-            Self::Proxied(proxied) => proxied.receive(),
+            Self::Proxied(proxied) => match proxied.receive()? {
+                None if proxied.eof() => Ok(Some(vec![])),
+                received => Ok(received),
+            },
+        }
+    }
+
+    /// None for direct connections.
+    pub fn stats(&self) -> Option<ProxyStats> {
+        match self {
+            Self::Direct(_) => None,
+            Self::Proxied(proxied) => Some(proxied.stats()),
         }
     }
 
@@ -250,9 +842,8 @@ impl UdpSocket {
     pub fn connect(address: SocketAddr, proxy: Option<&str>) -> Result<Self> {
         if let Some(proxy) = proxy {
             Ok(Self::Proxied(Proxied::connect(
-                address,
+                json!({ "Udp": address }),
                 proxy.to_owned(),
-                "Udp",
             )?))
         } else {
             let socket = net::UdpSocket::bind(unspecified_address(address))?;
@@ -262,6 +853,71 @@ impl UdpSocket {
         }
     }
 
+    /// Connects directly or through `proxy` as `mode` says. UDP has no handshake, so
+    /// [`ConnectMode::Auto`] only falls back to the proxy when no local socket can be bound, or on
+    /// wasm.
+    pub fn connect_with_mode(address: SocketAddr, proxy: &str, mode: ConnectMode) -> Result<Self> {
+        if mode.try_direct() {
+            match Self::connect(address, None) {
+                Ok(socket) => return Ok(socket),
+                Err(err) if mode == ConnectMode::Direct => return Err(err),
+                Err(_) => {}
+            }
+        }
+        Self::connect(address, Some(proxy))
+    }
+
+    /// Like [`connect_with_mode`](Self::connect_with_mode), with the mode from the
+    /// `WEBRTC_PROXY_MODE` environment variable.
+    pub fn connect_auto(address: SocketAddr, proxy: &str) -> Result<Self> {
+        Self::connect_with_mode(address, proxy, ConnectMode::from_env()?)
+    }
+
+    /// Connects through `proxy`, with `options` for the proxy's socket to the target.
+    pub fn connect_with_options(
+        address: SocketAddr,
+        options: &SocketOptions,
+        proxy: &str,
+    ) -> Result<Self> {
+        Ok(Self::Proxied(Proxied::connect(
+            json!({ "Udp": { "address": address, "options": options } }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    /// Connects through `proxy` to `address` given as `host:port`. The proxy resolves the host,
+    /// so names only it can resolve work, and nothing is looked up locally.
+    pub fn connect_host(address: &str, proxy: &str) -> Result<Self> {
+        Ok(Self::Proxied(Proxied::connect(
+            json!({ "Udp": address }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    /// Connects to a backend of a service configured on the proxy, which picks the backend.
+    pub fn connect_service(name: &str, proxy: &str) -> Result<Self> {
+        Ok(Self::Proxied(Proxied::connect(
+            json!({ "Service": name }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    /// Connects through the first proxy of `pool` that can be reached.
+    pub fn connect_pool(address: SocketAddr, pool: &ProxyPool) -> Result<Self> {
+        Ok(Self::Proxied(Proxied::connect_pool(
+            json!({ "Udp": address }),
+            pool,
+        )?))
+    }
+
+    /// The URL of the proxy in use, or None for direct connections.
+    pub fn proxy(&self) -> Option<&str> {
+        match self {
+            Self::Direct(_) => None,
+            Self::Proxied(proxied) => Some(proxied.proxy()),
+        }
+    }
+
     pub fn connected(&mut self, timeout: Duration) -> Result<bool> {
         match self {
             Self::Direct(socket) => {
@@ -323,6 +979,14 @@ impl UdpSocket {
         }
     }
 
+    /// None for direct connections.
+    pub fn stats(&self) -> Option<ProxyStats> {
+        match self {
+            Self::Direct(_) => None,
+            Self::Proxied(proxied) => Some(proxied.stats()),
+        }
+    }
+
     fn disconnect(&mut self) {
         match self {
             Self::Direct(socket) => *socket = None,
@@ -330,3 +994,211 @@ impl UdpSocket {
         }
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebSocketMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    Close(Option<(u16, String)>),
+}
+
+pub struct WebSocket(Proxied);
+
+impl WebSocket {
+    pub fn connect(
+        url: &str,
+        headers: &[(&str, &str)],
+        subprotocols: &[&str],
+        proxy: &str,
+    ) -> Result<Self> {
+        Ok(Self(Proxied::connect(
+            json!({
+                "WebSocket": {
+                    "url": url,
+                    "headers": headers,
+                    "subprotocols": subprotocols,
+                }
+            }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    pub fn connected(&mut self, timeout: Duration) -> Result<bool> {
+        self.0.connected(timeout)
+    }
+
+    pub fn send(&mut self, message: WebSocketMessage) -> Result<()> {
+        let data = match message {
+            WebSocketMessage::Text(text) => [&[0][..], text.as_bytes()].concat(),
+            WebSocketMessage::Binary(binary) => [&[1][..], &binary].concat(),
+            WebSocketMessage::Close(Some((code, reason))) => {
+                [&[2][..], &code.to_be_bytes(), reason.as_bytes()].concat()
+            }
+            WebSocketMessage::Close(None) => vec![2],
+        };
+        self.0.send(Packet::reliable(&data))
+    }
+
+    pub fn receive(&mut self) -> Result<Option<WebSocketMessage>> {
+        let Some(data) = self.0.receive()? else {
+            return Ok(None);
+        };
+        match data.split_first() {
+            Some((0, text)) => Ok(Some(WebSocketMessage::Text(
+                String::from_utf8_lossy(text).into_owned(),
+            ))),
+            Some((1, binary)) => Ok(Some(WebSocketMessage::Binary(binary.to_vec()))),
+            Some((2, close)) if close.len() >= 2 => Ok(Some(WebSocketMessage::Close(Some((
+                u16::from_be_bytes([close[0], close[1]]),
+                String::from_utf8_lossy(&close[2..]).into_owned(),
+            ))))),
+            Some((2, _)) => Ok(Some(WebSocketMessage::Close(None))),
+            _ => {
+                self.0.disconnect();
+                bail!("Invalid WebSocket message.");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HttpResponseHead {
+    pub status: u16,
+    pub status_text: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HttpEvent {
+    Head(HttpResponseHead),
+    Body(Vec<u8>),
+    End,
+}
+
+pub struct HttpRequest(Proxied);
+
+impl HttpRequest {
+    pub fn connect(
+        method: &str,
+        url: &str,
+        headers: &[(&str, &str)],
+        max_redirects: Option<u32>,
+        proxy: &str,
+    ) -> Result<Self> {
+        Ok(Self(Proxied::connect(
+            json!({
+                "Http": {
+                    "method": method,
+                    "url": url,
+                    "headers": headers,
+                    "max_redirects": max_redirects,
+                }
+            }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    pub fn connected(&mut self, timeout: Duration) -> Result<bool> {
+        self.0.connected(timeout)
+    }
+
+    pub fn send_body(&mut self, data: &[u8]) -> Result<()> {
+        self.0.send(Packet::reliable(&[&[0][..], data].concat()))
+    }
+
+    /// Ends the request body. The request isn't sent until this is called, even if it has no body.
+    pub fn finish(&mut self) -> Result<()> {
+        self.0.send(Packet::reliable(&[1]))
+    }
+
+    pub fn receive(&mut self) -> Result<Option<HttpEvent>> {
+        let Some(data) = self.0.receive()? else {
+            return Ok(None);
+        };
+        match data.split_first() {
+            Some((0, body)) => Ok(Some(HttpEvent::Body(body.to_vec()))),
+            Some((1, _)) => Ok(Some(HttpEvent::End)),
+            Some((2, head)) => Ok(Some(HttpEvent::Head(serde_json::from_slice(head)?))),
+            Some((3, error)) => {
+                self.0.disconnect();
+                bail!("{}", String::from_utf8_lossy(error));
+            }
+            _ => {
+                self.0.disconnect();
+                bail!("Invalid HTTP message.");
+            }
+        }
+    }
+}
+
+/// A TLS connection whose handshake runs on the proxy server, so only plaintext crosses the
+/// tunnel.
+pub struct TlsStream(Proxied);
+
+impl TlsStream {
+    pub fn connect(
+        address: &str,
+        sni: Option<&str>,
+        alpn: &[&str],
+        verify: bool,
+        proxy: &str,
+    ) -> Result<Self> {
+        Ok(Self(Proxied::connect(
+            json!({
+                "Tls": {
+                    "address": address,
+                    "sni": sni,
+                    "alpn": alpn,
+                    "verify": verify,
+                }
+            }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    pub fn connected(&mut self, timeout: Duration) -> Result<bool> {
+        self.0.connected(timeout)
+    }
+
+    pub fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.0.send(Packet::reliable(data))
+    }
+
+    pub fn receive(&mut self) -> Result<Option<Vec<u8>>> {
+        self.0.receive()
+    }
+}
+
+/// A channel of a kind the proxy's embedder registered, with messages whose meaning is up to it.
+pub struct CustomChannel(Proxied);
+
+impl CustomChannel {
+    pub fn connect(name: &str, config: &impl Serialize, proxy: &str) -> Result<Self> {
+        Ok(Self(Proxied::connect(
+            json!({
+                "Custom": {
+                    "name": name,
+                    "config": config,
+                }
+            }),
+            proxy.to_owned(),
+        )?))
+    }
+
+    pub fn connected(&mut self, timeout: Duration) -> Result<bool> {
+        self.0.connected(timeout)
+    }
+
+    pub fn send(&mut self, data: &[u8]) -> Result<()> {
+        self.0.send(Packet::reliable(data))
+    }
+
+    pub fn send_unreliable(&mut self, data: &[u8]) -> Result<()> {
+        self.0.send(Packet::unreliable(data))
+    }
+
+    pub fn receive(&mut self) -> Result<Option<Vec<u8>>> {
+        self.0.receive()
+    }
+}
@@ -0,0 +1,71 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use anyhow::{bail, Error, Result};
+
+/// Environment variable read by [`ConnectMode::from_env`]: `direct`, `proxied` or `auto`.
+pub const CONNECT_MODE_VAR: &str = "WEBRTC_PROXY_MODE";
+
+/// How long [`ConnectMode::Auto`] waits for a direct TCP connection before using the proxy.
+pub const DEFAULT_DIRECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Whether to connect directly or through the proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectMode {
+    /// Always connect directly, which fails on platforms without sockets like wasm.
+    Direct,
+    /// Always connect through the proxy.
+    Proxied,
+    /// Connect directly where the platform has sockets, and through the proxy on wasm or when the
+    /// direct connection fails or takes longer than `direct_timeout`.
+    Auto { direct_timeout: Duration },
+}
+
+impl Default for ConnectMode {
+    fn default() -> Self {
+        Self::Auto {
+            direct_timeout: DEFAULT_DIRECT_TIMEOUT,
+        }
+    }
+}
+
+impl ConnectMode {
+    /// The mode named by `WEBRTC_PROXY_MODE`, or [`ConnectMode::Auto`] if it isn't set or can't be
+    /// read, as on wasm.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(CONNECT_MODE_VAR) {
+            Ok(mode) => mode.parse(),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+
+    pub(crate) fn try_direct(&self) -> bool {
+        match self {
+            Self::Direct => true,
+            Self::Proxied => false,
+            Self::Auto { .. } => cfg!(not(target_arch = "wasm32")),
+        }
+    }
+}
+
+impl FromStr for ConnectMode {
+    type Err = Error;
+
+    fn from_str(mode: &str) -> Result<Self> {
+        Ok(match mode.trim().to_ascii_lowercase().as_str() {
+            "direct" => Self::Direct,
+            "proxied" | "proxy" => Self::Proxied,
+            "auto" | "" => Self::default(),
+            _ => bail!("Unknown connect mode {}.", mode),
+        })
+    }
+}
+
+impl fmt::Display for ConnectMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Direct => write!(f, "direct"),
+            Self::Proxied => write!(f, "proxied"),
+            Self::Auto { .. } => write!(f, "auto"),
+        }
+    }
+}
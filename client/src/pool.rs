@@ -0,0 +1,240 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::Result;
+use enaia_client::{EnaiaClient, EnaiaStatus, EnaiaUrl};
+use rusty_enet::{crc32, Event, Host, HostSettings, PeerID, RangeCoder};
+use web_time::Instant;
+
+use crate::DISCONNECT_CLOSED;
+
+/// A proxy that failed is tried after the others for this long.
+const FAILURE_MEMORY: Duration = Duration::from_secs(60);
+/// How long a probe stays connected before reading the round-trip time, so ENet has a few pings
+/// to go on.
+const PROBE_SETTLE_TIME: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyStrategy {
+    /// The proxies in the order given, moving on when one can't be reached.
+    Ordered,
+    /// The proxy with the lowest measured round-trip time first. Proxies that were never measured
+    /// come after the measured ones, in the order given, and ones that failed recently last.
+    LowestRtt,
+    /// A random proxy, avoiding ones that failed recently.
+    Random,
+}
+
+/// A set of proxy servers to choose from, remembering which ones failed and how fast the others
+/// were. Clones share that memory.
+#[derive(Clone)]
+pub struct ProxyPool(Arc<Mutex<ProxyPoolInner>>);
+
+struct ProxyPoolInner {
+    strategy: ProxyStrategy,
+    endpoints: Vec<ProxyEndpoint>,
+}
+
+struct ProxyEndpoint {
+    url: String,
+    round_trip_time: Option<Duration>,
+    failed_at: Option<Instant>,
+}
+
+impl ProxyEndpoint {
+    fn recently_failed(&self) -> bool {
+        self.failed_at
+            .is_some_and(|failed_at| failed_at.elapsed() < FAILURE_MEMORY)
+    }
+}
+
+impl ProxyPool {
+    pub fn new(
+        proxies: impl IntoIterator<Item = impl Into<String>>,
+        strategy: ProxyStrategy,
+    ) -> Self {
+        Self(Arc::new(Mutex::new(ProxyPoolInner {
+            strategy,
+            endpoints: proxies
+                .into_iter()
+                .map(|url| ProxyEndpoint {
+                    url: url.into(),
+                    round_trip_time: None,
+                    failed_at: None,
+                })
+                .collect(),
+        })))
+    }
+
+    /// Every proxy in the order they should be tried.
+    pub fn candidates(&self) -> Vec<String> {
+        let inner = self.0.lock().unwrap();
+        let mut endpoints = inner.endpoints.iter().collect::<Vec<_>>();
+        match inner.strategy {
+            ProxyStrategy::Ordered => {}
+            ProxyStrategy::LowestRtt => endpoints.sort_by_key(|endpoint| {
+                (
+                    endpoint.recently_failed(),
+                    endpoint.round_trip_time.is_none(),
+                    endpoint.round_trip_time,
+                )
+            }),
+            ProxyStrategy::Random => {
+                shuffle(&mut endpoints);
+                endpoints.sort_by_key(|endpoint| endpoint.recently_failed());
+            }
+        }
+        endpoints
+            .into_iter()
+            .map(|endpoint| endpoint.url.clone())
+            .collect()
+    }
+
+    /// The latest round-trip time measured to `proxy`, by a probe or a connection through it.
+    pub fn round_trip_time(&self, proxy: &str) -> Option<Duration> {
+        let inner = self.0.lock().unwrap();
+        inner
+            .endpoints
+            .iter()
+            .find(|endpoint| endpoint.url == proxy)
+            .and_then(|endpoint| endpoint.round_trip_time)
+    }
+
+    /// Connects to every proxy at once to measure round-trip times for
+    /// [`ProxyStrategy::LowestRtt`]. Proxies that can't be reached within `timeout` count as
+    /// failed.
+    pub fn probe(&self, timeout: Duration) -> Result<ProxyProbe> {
+        ProxyProbe::new(self.clone(), timeout)
+    }
+
+    pub(crate) fn report_success(&self, proxy: &str, round_trip_time: Duration) {
+        let mut inner = self.0.lock().unwrap();
+        if let Some(endpoint) = inner
+            .endpoints
+            .iter_mut()
+            .find(|endpoint| endpoint.url == proxy)
+        {
+            endpoint.round_trip_time = Some(round_trip_time);
+            endpoint.failed_at = None;
+        }
+    }
+
+    pub(crate) fn report_failure(&self, proxy: &str) {
+        let mut inner = self.0.lock().unwrap();
+        if let Some(endpoint) = inner
+            .endpoints
+            .iter_mut()
+            .find(|endpoint| endpoint.url == proxy)
+        {
+            endpoint.failed_at = Some(Instant::now());
+        }
+    }
+}
+
+fn shuffle<T>(items: &mut [T]) {
+    for index in (1..items.len()).rev() {
+        let mut bytes = [0; 4];
+        _ = getrandom::getrandom(&mut bytes);
+        items.swap(index, u32::from_ne_bytes(bytes) as usize % (index + 1));
+    }
+}
+
+/// Measures the round-trip time to every proxy in a pool without blocking. Call
+/// [`poll`](Self::poll) until it returns true.
+pub struct ProxyProbe {
+    pool: ProxyPool,
+    status: EnaiaStatus,
+    host: Host<EnaiaClient>,
+    pending: HashMap<PeerID, (String, Option<Instant>)>,
+    started: Instant,
+    timeout: Duration,
+}
+
+impl ProxyProbe {
+    fn new(pool: ProxyPool, timeout: Duration) -> Result<Self> {
+        let proxies = pool.candidates();
+        let client = EnaiaClient::new();
+        let status = client.status();
+        let mut host = Host::<EnaiaClient>::create(
+            client,
+            HostSettings {
+                peer_limit: proxies.len().max(1),
+                channel_limit: 1,
+                compressor: Some(Box::new(RangeCoder::new())),
+                checksum: Some(Box::new(crc32)),
+                ..Default::default()
+            },
+        )?;
+        let mut pending = HashMap::new();
+        for proxy in proxies {
+            let peer = host.connect(EnaiaUrl::from(proxy.clone()), 1, 0)?.id();
+            pending.insert(peer, (proxy, None));
+        }
+        Ok(Self {
+            pool,
+            status,
+            host,
+            pending,
+            started: Instant::now(),
+            timeout,
+        })
+    }
+
+    /// True once every proxy was measured or gave up on.
+    pub fn poll(&mut self) -> Result<bool> {
+        while let Some(event) = self.host.service()? {
+            match event {
+                Event::Connect { peer, .. } => {
+                    if let Some((_, connected_at)) = self.pending.get_mut(&peer.id()) {
+                        *connected_at = Some(Instant::now());
+                    }
+                }
+                Event::Disconnect { peer, .. } => {
+                    if let Some((proxy, _)) = self.pending.remove(&peer.id()) {
+                        self.pool.report_failure(&proxy);
+                    }
+                }
+                Event::Receive { .. } => {}
+            }
+        }
+        let timed_out = self.started.elapsed() > self.timeout;
+        let mut finished = vec![];
+        for (peer_id, (proxy, connected_at)) in &self.pending {
+            let url = EnaiaUrl::from(proxy.as_str());
+            match connected_at {
+                Some(connected_at) if connected_at.elapsed() > PROBE_SETTLE_TIME || timed_out => {
+                    if let Ok(peer) = self.host.peer_mut(*peer_id) {
+                        self.pool.report_success(proxy, peer.round_trip_time());
+                        peer.disconnect(DISCONNECT_CLOSED);
+                    }
+                    finished.push(*peer_id);
+                }
+                Some(_) => {}
+                None if timed_out
+                    || self
+                        .status
+                        .state(&url)
+                        .is_some_and(|state| state.is_failed()) =>
+                {
+                    self.pool.report_failure(proxy);
+                    if let Ok(peer) = self.host.peer_mut(*peer_id) {
+                        peer.reset();
+                    }
+                    finished.push(*peer_id);
+                }
+                None => {}
+            }
+        }
+        for peer_id in finished {
+            self.pending.remove(&peer_id);
+        }
+        if self.pending.is_empty() {
+            // Sends the disconnects.
+            _ = self.host.flush();
+        }
+        Ok(self.pending.is_empty())
+    }
+}
@@ -0,0 +1,238 @@
+use std::{collections::VecDeque, time::Duration};
+
+use rusty_enet::{Packet, PacketKind};
+use web_time::Instant;
+
+use crate::{FRAME_ACK, FRAME_SESSION};
+
+/// How often we acknowledge what we received, so the server can stop keeping it.
+const ACK_INTERVAL: Duration = Duration::from_millis(100);
+/// Most data kept until the server acknowledges it. A tunnel that needs more can't be resumed.
+const MAX_UNACKED_BYTES: usize = 4 * 1024 * 1024;
+
+/// What resuming the tunnel takes: the token the server gave us, and every reliable packet we sent
+/// until the server acknowledges it, to be sent again after a resume. Session frames and
+/// unreliable packets aren't counted.
+pub(crate) struct Session {
+    /// Set once the server sent it.
+    token: Option<[u8; 16]>,
+    /// Sent and not acknowledged yet, oldest first.
+    unacked: VecDeque<Vec<u8>>,
+    unacked_bytes: usize,
+    /// How many were acknowledged, which were all sent before the first of `unacked`.
+    acked: u32,
+    received: u32,
+    /// `received` as of the last acknowledgement sent.
+    received_acked: u32,
+    acked_at: Instant,
+    /// More than we may keep went unacknowledged, so nothing is kept anymore. Servers that don't
+    /// support resumption never acknowledge anything.
+    overflowed: bool,
+}
+
+fn counted(packet: &Packet) -> bool {
+    matches!(packet.kind(), PacketKind::Reliable) && packet.data().first() != Some(&FRAME_SESSION)
+}
+
+impl Session {
+    pub(crate) fn new() -> Self {
+        Self {
+            token: None,
+            unacked: VecDeque::new(),
+            unacked_bytes: 0,
+            acked: 0,
+            received: 0,
+            received_acked: 0,
+            acked_at: Instant::now(),
+            overflowed: false,
+        }
+    }
+
+    /// True if the server gave us a token and we still have everything it didn't acknowledge.
+    pub(crate) fn resumable(&self) -> bool {
+        self.token.is_some() && !self.overflowed
+    }
+
+    /// Takes the token from the server's session frame.
+    pub(crate) fn set_token(&mut self, data: &[u8]) {
+        self.token = data.try_into().ok();
+    }
+
+    /// Keeps `packet` until the server acknowledges it, if it's counted.
+    pub(crate) fn sent(&mut self, packet: &Packet) {
+        if counted(packet) && !self.overflowed {
+            self.unacked_bytes += packet.data().len();
+            self.unacked.push_back(packet.data().to_vec());
+            if self.unacked_bytes > MAX_UNACKED_BYTES {
+                self.overflowed = true;
+                self.unacked.clear();
+                self.unacked_bytes = 0;
+            }
+        }
+    }
+
+    pub(crate) fn received(&mut self, packet: &Packet) {
+        if counted(packet) {
+            self.received = self.received.wrapping_add(1);
+        }
+    }
+
+    /// Stops keeping the packets the server has, given how many it received. Returns false if
+    /// that's more than were sent, or fewer than it acknowledged before.
+    pub(crate) fn acknowledge(&mut self, received: u32) -> bool {
+        let acked = received.wrapping_sub(self.acked) as usize;
+        if acked > self.unacked.len() {
+            return false;
+        }
+        for data in self.unacked.drain(..acked) {
+            self.unacked_bytes -= data.len();
+        }
+        self.acked = received;
+        true
+    }
+
+    /// The session frame that resumes the tunnel: the token, then channel 0 and how many packets
+    /// we received on it.
+    pub(crate) fn resume_request(&self) -> Option<Packet> {
+        let mut data = vec![FRAME_SESSION];
+        data.extend(self.token?);
+        data.push(0);
+        data.extend(self.received.to_be_bytes());
+        Some(Packet::reliable(&data))
+    }
+
+    /// Takes the server's answer to [`resume_request`](Self::resume_request), with how many
+    /// packets it received on each channel, and returns the packets it's missing. None if its
+    /// counts don't fit what we sent.
+    pub(crate) fn resume(&mut self, counts: &[u8]) -> Option<Vec<Packet>> {
+        if !counts.len().is_multiple_of(5) {
+            return None;
+        }
+        let received = counts
+            .chunks(5)
+            .find(|chunk| chunk[0] == 0)
+            .map_or(0, |chunk| {
+                u32::from_be_bytes([chunk[1], chunk[2], chunk[3], chunk[4]])
+            });
+        if !self.acknowledge(received) {
+            return None;
+        }
+        Some(
+            self.unacked
+                .iter()
+                .map(|data| Packet::reliable(data))
+                .collect(),
+        )
+    }
+
+    /// An acknowledgement for what we received since the last one, at most every
+    /// [`ACK_INTERVAL`], if the server gave us a token and so takes them. It's unreliable, since
+    /// a later one supersedes a lost one.
+    pub(crate) fn ack(&mut self) -> Option<Packet> {
+        if self.token.is_none()
+            || self.received == self.received_acked
+            || self.acked_at.elapsed() < ACK_INTERVAL
+        {
+            return None;
+        }
+        self.acked_at = Instant::now();
+        self.received_acked = self.received;
+        let mut data = vec![FRAME_ACK];
+        data.extend(self.received.to_be_bytes());
+        Some(Packet::unreliable(&data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: [u8; 16] = [7; 16];
+
+    fn with_token() -> Session {
+        let mut session = Session::new();
+        session.set_token(&TOKEN);
+        session
+    }
+
+    #[test]
+    fn acknowledged_packets_are_no_longer_kept() {
+        let mut session = with_token();
+        for data in [b"a", b"b", b"c"] {
+            session.sent(&Packet::reliable(data));
+        }
+        assert!(session.acknowledge(2));
+        assert_eq!(session.unacked_bytes, 1);
+        let missing = session.resume(&[0, 0, 0, 0, 2]).unwrap();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].data(), b"c");
+    }
+
+    #[test]
+    fn acknowledging_more_than_was_sent_or_going_back_fails() {
+        let mut session = with_token();
+        session.sent(&Packet::reliable(b"a"));
+        session.sent(&Packet::reliable(b"b"));
+        assert!(!session.acknowledge(3));
+        assert!(session.acknowledge(2));
+        assert!(!session.acknowledge(1));
+    }
+
+    #[test]
+    fn session_frames_and_unreliable_packets_are_not_counted() {
+        let mut session = with_token();
+        session.sent(&Packet::reliable(&[FRAME_SESSION, 1]));
+        session.sent(&Packet::unreliable(b"a"));
+        session.received(&Packet::reliable(&[FRAME_SESSION]));
+        session.received(&Packet::unreliable(b"a"));
+        assert_eq!(session.unacked_bytes, 0);
+        assert_eq!(session.received, 0);
+    }
+
+    #[test]
+    fn overflowing_keeps_nothing_and_ends_resumption() {
+        let mut session = with_token();
+        assert!(session.resumable());
+        session.sent(&Packet::reliable(&vec![0; MAX_UNACKED_BYTES]));
+        assert!(session.resumable());
+        session.sent(&Packet::reliable(b"a"));
+        assert!(!session.resumable());
+        assert!(session.unacked.is_empty());
+        session.sent(&Packet::reliable(b"b"));
+        assert_eq!(session.unacked_bytes, 0);
+    }
+
+    #[test]
+    fn resume_request_has_the_token_and_received_count() {
+        let mut session = Session::new();
+        assert!(session.resume_request().is_none());
+        session.set_token(&TOKEN);
+        session.received(&Packet::reliable(b"a"));
+        let mut expected = vec![FRAME_SESSION];
+        expected.extend(TOKEN);
+        expected.extend([0, 0, 0, 0, 1]);
+        assert_eq!(session.resume_request().unwrap().data(), expected);
+    }
+
+    #[test]
+    fn resume_fails_on_counts_that_dont_fit() {
+        let mut session = with_token();
+        session.sent(&Packet::reliable(b"a"));
+        assert!(session.resume(&[0, 0, 0]).is_none());
+        assert!(session.resume(&[0, 0, 0, 0, 2]).is_none());
+        // Channels other than 0 are ignored, and a missing count is nothing received.
+        assert_eq!(session.resume(&[1, 0, 0, 0, 5]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn acks_need_a_token() {
+        let mut session = Session::new();
+        session.received(&Packet::reliable(b"a"));
+        session.acked_at = Instant::now() - ACK_INTERVAL;
+        assert!(session.ack().is_none());
+        session.set_token(&TOKEN);
+        assert_eq!(session.ack().unwrap().data(), [FRAME_ACK, 0, 0, 0, 1]);
+        session.acked_at = Instant::now() - ACK_INTERVAL;
+        assert!(session.ack().is_none());
+    }
+}
@@ -0,0 +1,3 @@
+use naia_client_socket::NaiaClientSocketError;
+
+pub type EnaiaError = enaia_shared::EnaiaError<NaiaClientSocketError>;
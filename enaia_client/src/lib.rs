@@ -1,6 +1,20 @@
-use naia_client_socket::{NaiaClientSocketError, PacketReceiver, PacketSender, Socket};
-use naia_socket_shared::{LinkConditionerConfig, SocketConfig};
+use std::collections::HashMap;
 
+use naia_client_socket::{PacketReceiver, PacketSender, ServerAddr, Socket};
+use web_time::Instant;
+
+mod error;
+mod status;
+mod websocket;
+
+pub use enaia_shared::{EnaiaConfig, LinkConditionerConfig, DEFAULT_MAX_DATAGRAM_SIZE};
+pub use error::*;
+pub use status::*;
+
+use websocket::{is_websocket_url, WebSocketTransport};
+
+/// A server's WebRTC session URL, e.g. `https://example.com:14191`, or its WebSocket URL, e.g.
+/// `wss://example.com:14193`, for networks that block WebRTC.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EnaiaUrl(pub String);
 
@@ -30,80 +44,256 @@ impl rusty_enet::Address for EnaiaUrl {
     }
 }
 
-pub enum EnaiaClient {
-    Disconnected,
-    Connected {
-        server_address: EnaiaUrl,
+/// A naia client socket or WebSocket per server URL, so one host can hold peers on several
+/// servers.
+pub struct EnaiaClient {
+    config: EnaiaConfig,
+    connections: Vec<EnaiaConnection>,
+    next_receive: usize,
+    status: EnaiaStatus,
+}
+
+struct EnaiaConnection {
+    server_address: EnaiaUrl,
+    transport: Transport,
+    state: EnaiaConnectionState,
+    established: bool,
+    created: Instant,
+    last_received: Instant,
+    /// When each ENet peer using the connection last sent on it, by its peer ID on the server.
+    peers: HashMap<u16, Instant>,
+}
+
+impl EnaiaConnection {
+    fn new(server_address: EnaiaUrl, config: &EnaiaConfig, status: &EnaiaStatus) -> Self {
+        let transport = if is_websocket_url(&server_address.0) {
+            Transport::WebSocket(WebSocketTransport::connect(&server_address.0))
+        } else {
+            let (packet_sender, packet_receiver) =
+                Socket::connect(&server_address.0, &config.socket_config());
+            Transport::WebRtc {
+                packet_sender,
+                packet_receiver,
+            }
+        };
+        status.set_state(&server_address, EnaiaConnectionState::Connecting);
+        Self {
+            server_address,
+            transport,
+            state: EnaiaConnectionState::Connecting,
+            established: false,
+            created: Instant::now(),
+            last_received: Instant::now(),
+            peers: HashMap::new(),
+        }
+    }
+
+    /// Whether any ENet peer still uses the connection. ENet pings twice a second, so a peer
+    /// that hasn't sent anything for the transport timeout has disconnected.
+    fn in_use(&mut self, config: &EnaiaConfig) -> bool {
+        self.peers
+            .retain(|_, last_sent| last_sent.elapsed() < config.transport_timeout);
+        !self.peers.is_empty()
+    }
+
+    fn set_state(&mut self, state: EnaiaConnectionState, status: &EnaiaStatus) {
+        self.state = state;
+        status.set_state(&self.server_address, state);
+    }
+
+    fn update_state(&mut self, config: &EnaiaConfig, status: &EnaiaStatus) {
+        match self.state {
+            EnaiaConnectionState::Connecting => {
+                if self.transport.is_open() {
+                    self.last_received = Instant::now();
+                    self.set_state(EnaiaConnectionState::Connected, status);
+                } else if self.created.elapsed() > config.signaling_timeout {
+                    self.set_state(EnaiaConnectionState::SignalingFailed, status);
+                }
+            }
+            EnaiaConnectionState::Connected => {
+                if self.last_received.elapsed() > config.transport_timeout {
+                    self.set_state(EnaiaConnectionState::TransportLost, status);
+                }
+            }
+            EnaiaConnectionState::SignalingFailed | EnaiaConnectionState::TransportLost => {}
+        }
+    }
+
+    fn fail(&mut self, message: String, status: &EnaiaStatus) {
+        status.error(&self.server_address, message);
+        match self.state {
+            EnaiaConnectionState::Connecting => {
+                self.set_state(EnaiaConnectionState::SignalingFailed, status)
+            }
+            EnaiaConnectionState::Connected => {
+                self.set_state(EnaiaConnectionState::TransportLost, status)
+            }
+            EnaiaConnectionState::SignalingFailed | EnaiaConnectionState::TransportLost => {}
+        }
+    }
+}
+
+enum Transport {
+    WebRtc {
         packet_sender: Box<dyn PacketSender>,
         packet_receiver: Box<dyn PacketReceiver>,
     },
+    WebSocket(WebSocketTransport),
+}
+
+impl Transport {
+    fn is_open(&self) -> bool {
+        match self {
+            Self::WebRtc {
+                packet_receiver, ..
+            } => matches!(packet_receiver.server_addr(), ServerAddr::Found(_)),
+            Self::WebSocket(transport) => transport.is_open(),
+        }
+    }
+
+    fn send(&mut self, buffer: &[u8]) -> Result<(), EnaiaError> {
+        match self {
+            Self::WebRtc { packet_sender, .. } => Ok(packet_sender.send(buffer)?),
+            Self::WebSocket(transport) => {
+                transport.send(buffer);
+                Ok(())
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Result<Option<Vec<u8>>, EnaiaError> {
+        match self {
+            Self::WebRtc {
+                packet_receiver, ..
+            } => Ok(packet_receiver.receive()?.map(Vec::from)),
+            Self::WebSocket(transport) => transport.receive().map_err(EnaiaError::WebSocket),
+        }
+    }
+}
+
+const PEER_ID_MASK: u16 = 0x0fff;
+
+/// The peer ID the server assigned to the ENet peer that sent `buffer`, from its header.
+fn peer_id(buffer: &[u8]) -> Option<u16> {
+    (buffer.len() >= 2).then(|| u16::from_be_bytes([buffer[0], buffer[1]]) & PEER_ID_MASK)
+}
+
+/// ENet sends connection requests before the server has assigned an outgoing peer ID, so the
+/// header carries the maximum peer ID instead.
+fn is_connect_request(buffer: &[u8]) -> bool {
+    peer_id(buffer) == Some(PEER_ID_MASK)
+}
+
+impl Default for EnaiaClient {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl EnaiaClient {
     pub fn new() -> Self {
-        Self::Disconnected
+        Self::with_config(EnaiaConfig::default())
+    }
+
+    pub fn with_config(config: EnaiaConfig) -> Self {
+        Self {
+            config,
+            connections: vec![],
+            next_receive: 0,
+            status: EnaiaStatus::default(),
+        }
+    }
+
+    /// Returns a handle for querying connection states and errors, which keeps working after the
+    /// client is handed to a `rusty_enet::Host`.
+    pub fn status(&self) -> EnaiaStatus {
+        self.status.clone()
     }
 }
 
 impl rusty_enet::Socket for EnaiaClient {
     type PeerAddress = EnaiaUrl;
-    type Error = NaiaClientSocketError;
+    type Error = EnaiaError;
 
-    fn init(&mut self, _options: rusty_enet::SocketOptions) -> Result<(), NaiaClientSocketError> {
+    fn init(&mut self, _options: rusty_enet::SocketOptions) -> Result<(), EnaiaError> {
         Ok(())
     }
 
-    fn send(
-        &mut self,
-        address: Self::PeerAddress,
-        buffer: &[u8],
-    ) -> Result<usize, NaiaClientSocketError> {
-        if matches!(self, EnaiaClient::Disconnected) {
-            let (packet_sender, packet_receiver) = Socket::connect(
-                &address.0,
-                &SocketConfig::new(Some(LinkConditionerConfig::new(0, 0, 0.)), None),
-            );
-            *self = EnaiaClient::Connected {
-                server_address: address,
-                packet_sender,
-                packet_receiver,
-            }
+    fn send(&mut self, address: Self::PeerAddress, buffer: &[u8]) -> Result<usize, EnaiaError> {
+        if buffer.len() > self.config.max_datagram_size {
+            return Err(EnaiaError::PacketTooLarge {
+                size: buffer.len(),
+                max: self.config.max_datagram_size,
+            });
         }
-
-        if let EnaiaClient::Connected {
-            server_address: _,
-            packet_sender,
-            packet_receiver: _,
-        } = self
+        let index = match self
+            .connections
+            .iter()
+            .position(|connection| connection.server_address == address)
         {
-            packet_sender.send(buffer)?;
-            Ok(buffer.len())
-        } else {
-            Err(NaiaClientSocketError::SendError)
+            // A connection request to a server we've given up on, or whose other peers have all
+            // left, may find its WebRTC session gone too, so start over with a new one. Peers
+            // still using the connection keep it.
+            Some(index)
+                if is_connect_request(buffer)
+                    && (self.connections[index].state.is_failed()
+                        || (self.connections[index].established
+                            && !self.connections[index].in_use(&self.config))) =>
+            {
+                self.connections[index] = EnaiaConnection::new(address, &self.config, &self.status);
+                index
+            }
+            Some(index) => index,
+            None => {
+                self.connections
+                    .push(EnaiaConnection::new(address, &self.config, &self.status));
+                self.connections.len() - 1
+            }
+        };
+        let connection = &mut self.connections[index];
+        if let Some(peer_id) = peer_id(buffer).filter(|&peer_id| peer_id != PEER_ID_MASK) {
+            connection.peers.insert(peer_id, Instant::now());
         }
+        if let Err(err) = connection.transport.send(buffer) {
+            connection.fail(err.to_string(), &self.status);
+            return Err(err);
+        }
+        Ok(buffer.len())
     }
 
     fn receive(
         &mut self,
-        _mtu: usize,
-    ) -> Result<Option<(Self::PeerAddress, rusty_enet::PacketReceived)>, NaiaClientSocketError>
-    {
-        if let EnaiaClient::Connected {
-            server_address,
-            packet_sender: _,
-            packet_receiver,
-        } = self
-        {
-            match packet_receiver.receive() {
-                Ok(Some(payload)) => Ok(Some((
-                    server_address.clone(),
-                    rusty_enet::PacketReceived::Complete(Vec::from(payload)),
-                ))),
-                Ok(None) => Ok(None),
-                Err(err) => Err(err.into()),
+        mtu: usize,
+    ) -> Result<Option<(Self::PeerAddress, rusty_enet::PacketReceived)>, EnaiaError> {
+        for connection in &mut self.connections {
+            connection.update_state(&self.config, &self.status);
+        }
+        // Start from a different connection each time so a busy server can't starve the rest.
+        for _ in 0..self.connections.len() {
+            let index = self.next_receive % self.connections.len();
+            self.next_receive = index + 1;
+            let connection = &mut self.connections[index];
+            if connection.state.is_failed() {
+                continue;
+            }
+            match connection.transport.receive() {
+                Ok(Some(payload)) => {
+                    connection.established = true;
+                    connection.last_received = Instant::now();
+                    if connection.state == EnaiaConnectionState::Connecting {
+                        connection.set_state(EnaiaConnectionState::Connected, &self.status);
+                    }
+                    return Ok(Some((
+                        connection.server_address.clone(),
+                        enaia_shared::received(payload, mtu),
+                    )));
+                }
+                Ok(None) => {}
+                // Only the peer on this server is affected, and ENet times it out on its own.
+                Err(err) => connection.fail(err.to_string(), &self.status),
             }
-        } else {
-            Ok(None)
         }
+        Ok(None)
     }
 }
@@ -0,0 +1,98 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use crate::EnaiaUrl;
+
+/// Events nobody polls for are dropped, oldest first, past this many.
+const MAX_EVENTS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnaiaConnectionState {
+    /// Signaling with the server and negotiating the WebRTC session, or opening the WebSocket.
+    Connecting,
+    Connected,
+    /// The signaling request failed, or ICE didn't finish within the signaling timeout. For the
+    /// WebSocket transport, the handshake failed or timed out.
+    SignalingFailed,
+    /// The WebRTC session or WebSocket was up, then stopped delivering packets.
+    TransportLost,
+}
+
+impl EnaiaConnectionState {
+    pub fn is_failed(self) -> bool {
+        matches!(self, Self::SignalingFailed | Self::TransportLost)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnaiaEvent {
+    StateChanged {
+        server_address: EnaiaUrl,
+        state: EnaiaConnectionState,
+    },
+    Error {
+        server_address: EnaiaUrl,
+        message: String,
+    },
+}
+
+/// A handle to the connection states of an [`EnaiaClient`](crate::EnaiaClient), which stays
+/// usable after the client is moved into a `rusty_enet::Host`.
+#[derive(Clone, Default)]
+pub struct EnaiaStatus(Arc<Mutex<EnaiaStatusInner>>);
+
+#[derive(Default)]
+struct EnaiaStatusInner {
+    states: Vec<(EnaiaUrl, EnaiaConnectionState)>,
+    events: VecDeque<EnaiaEvent>,
+}
+
+impl EnaiaStatus {
+    pub fn state(&self, server_address: &EnaiaUrl) -> Option<EnaiaConnectionState> {
+        let inner = self.0.lock().unwrap();
+        inner
+            .states
+            .iter()
+            .find(|(address, _)| address == server_address)
+            .map(|(_, state)| *state)
+    }
+
+    pub fn poll_event(&self) -> Option<EnaiaEvent> {
+        self.0.lock().unwrap().events.pop_front()
+    }
+
+    pub(crate) fn set_state(&self, server_address: &EnaiaUrl, state: EnaiaConnectionState) {
+        let mut inner = self.0.lock().unwrap();
+        match inner
+            .states
+            .iter_mut()
+            .find(|(address, _)| address == server_address)
+        {
+            Some((_, current)) if *current == state => return,
+            Some((_, current)) => *current = state,
+            None => inner.states.push((server_address.clone(), state)),
+        }
+        inner.push_event(EnaiaEvent::StateChanged {
+            server_address: server_address.clone(),
+            state,
+        });
+    }
+
+    pub(crate) fn error(&self, server_address: &EnaiaUrl, message: String) {
+        self.0.lock().unwrap().push_event(EnaiaEvent::Error {
+            server_address: server_address.clone(),
+            message,
+        });
+    }
+}
+
+impl EnaiaStatusInner {
+    fn push_event(&mut self, event: EnaiaEvent) {
+        if self.events.len() == MAX_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(event);
+    }
+}
@@ -0,0 +1,266 @@
+pub(crate) use transport::WebSocketTransport;
+
+pub(crate) fn is_websocket_url(url: &str) -> bool {
+    url.starts_with("ws://") || url.starts_with("wss://")
+}
+
+enum WebSocketEvent {
+    Open,
+    Message(Vec<u8>),
+    Error(String),
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod transport {
+    use std::{
+        io::ErrorKind,
+        sync::{
+            mpsc::{self, Receiver, Sender, TryRecvError},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use tungstenite::{stream::MaybeTlsStream, Error, Message};
+
+    use super::WebSocketEvent;
+
+    /// A WebSocket carrying one ENet datagram per binary message, driven by its own thread.
+    pub(crate) struct WebSocketTransport {
+        outgoing: Sender<Vec<u8>>,
+        /// Behind a mutex only to be `Sync`, like the naia socket, so hosts can be shared between
+        /// threads. It's never contended, since receiving takes `&mut self`.
+        incoming: Mutex<Receiver<WebSocketEvent>>,
+        open: bool,
+    }
+
+    // Fails to compile if the transport stops being shareable, as in the Bevy example.
+    const _: fn() = || {
+        fn send_sync<T: Send + Sync>() {}
+        send_sync::<WebSocketTransport>();
+    };
+
+    impl WebSocketTransport {
+        pub(crate) fn connect(url: &str) -> Self {
+            let (outgoing, outgoing_receiver) = mpsc::channel();
+            let (incoming_sender, incoming) = mpsc::channel();
+            let url = url.to_owned();
+            std::thread::spawn(move || {
+                if let Err(err) = run(&url, outgoing_receiver, &incoming_sender) {
+                    _ = incoming_sender.send(WebSocketEvent::Error(err.to_string()));
+                }
+            });
+            Self {
+                outgoing,
+                incoming: Mutex::new(incoming),
+                open: false,
+            }
+        }
+
+        pub(crate) fn is_open(&self) -> bool {
+            self.open
+        }
+
+        pub(crate) fn send(&mut self, buffer: &[u8]) {
+            // Sends before the handshake finishes wait in the channel; if the thread is gone, the
+            // error has already been reported through `receive`.
+            _ = self.outgoing.send(buffer.to_vec());
+        }
+
+        pub(crate) fn receive(&mut self) -> Result<Option<Vec<u8>>, String> {
+            let incoming = self.incoming.get_mut().unwrap();
+            loop {
+                match incoming.try_recv() {
+                    Ok(WebSocketEvent::Open) => self.open = true,
+                    Ok(WebSocketEvent::Message(data)) => return Ok(Some(data)),
+                    Ok(WebSocketEvent::Error(message)) => {
+                        self.open = false;
+                        return Err(message);
+                    }
+                    Err(_) => return Ok(None),
+                }
+            }
+        }
+    }
+
+    fn run(
+        url: &str,
+        outgoing: Receiver<Vec<u8>>,
+        incoming: &Sender<WebSocketEvent>,
+    ) -> tungstenite::Result<()> {
+        let (mut socket, _) = tungstenite::connect(url)?;
+        match socket.get_mut() {
+            MaybeTlsStream::Plain(stream) => stream.set_nonblocking(true)?,
+            MaybeTlsStream::Rustls(stream) => stream.get_mut().set_nonblocking(true)?,
+            _ => {}
+        }
+        if incoming.send(WebSocketEvent::Open).is_err() {
+            return Ok(());
+        }
+        loop {
+            let mut idle = true;
+            loop {
+                match outgoing.try_recv() {
+                    Ok(data) => {
+                        idle = false;
+                        ignore_would_block(socket.write(Message::Binary(data)))?;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    // The transport was dropped.
+                    Err(TryRecvError::Disconnected) => {
+                        _ = socket.close(None);
+                        _ = socket.flush();
+                        return Ok(());
+                    }
+                }
+            }
+            ignore_would_block(socket.flush())?;
+            match socket.read() {
+                Ok(Message::Binary(data)) => {
+                    idle = false;
+                    if incoming.send(WebSocketEvent::Message(data)).is_err() {
+                        return Ok(());
+                    }
+                }
+                Ok(_) => idle = false,
+                Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err),
+            }
+            if idle {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    fn ignore_would_block(result: tungstenite::Result<()>) -> tungstenite::Result<()> {
+        match result {
+            Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => Ok(()),
+            result => result,
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod transport {
+    use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+    use wasm_bindgen::{closure::Closure, JsCast};
+    use web_sys::{BinaryType, Event, MessageEvent, WebSocket};
+
+    use super::WebSocketEvent;
+
+    /// A browser WebSocket carrying one ENet datagram per binary message.
+    pub(crate) struct WebSocketTransport {
+        socket: Option<WebSocket>,
+        events: Rc<RefCell<VecDeque<WebSocketEvent>>>,
+        open: bool,
+        _on_open: Closure<dyn FnMut(Event)>,
+        _on_message: Closure<dyn FnMut(MessageEvent)>,
+        _on_error: Closure<dyn FnMut(Event)>,
+        _on_close: Closure<dyn FnMut(Event)>,
+    }
+
+    impl WebSocketTransport {
+        pub(crate) fn connect(url: &str) -> Self {
+            let events = Rc::new(RefCell::new(VecDeque::new()));
+            let on_open = {
+                let events = events.clone();
+                Closure::<dyn FnMut(Event)>::new(move |_| {
+                    events.borrow_mut().push_back(WebSocketEvent::Open);
+                })
+            };
+            let on_message = {
+                let events = events.clone();
+                Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+                    if let Ok(buffer) = event.data().dyn_into::<js_sys::ArrayBuffer>() {
+                        let data = js_sys::Uint8Array::new(&buffer).to_vec();
+                        events.borrow_mut().push_back(WebSocketEvent::Message(data));
+                    }
+                })
+            };
+            // Browsers deliberately hide why a WebSocket failed, so there's nothing more specific
+            // to report.
+            let on_error = {
+                let events = events.clone();
+                Closure::<dyn FnMut(Event)>::new(move |_| {
+                    events
+                        .borrow_mut()
+                        .push_back(WebSocketEvent::Error("WebSocket error.".to_owned()));
+                })
+            };
+            let on_close = {
+                let events = events.clone();
+                Closure::<dyn FnMut(Event)>::new(move |_| {
+                    events
+                        .borrow_mut()
+                        .push_back(WebSocketEvent::Error("WebSocket closed.".to_owned()));
+                })
+            };
+            let socket = match WebSocket::new(url) {
+                Ok(socket) => {
+                    socket.set_binary_type(BinaryType::Arraybuffer);
+                    socket.set_onopen(Some(on_open.as_ref().unchecked_ref()));
+                    socket.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+                    socket.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+                    socket.set_onclose(Some(on_close.as_ref().unchecked_ref()));
+                    Some(socket)
+                }
+                Err(_) => {
+                    events
+                        .borrow_mut()
+                        .push_back(WebSocketEvent::Error("Invalid WebSocket URL.".to_owned()));
+                    None
+                }
+            };
+            Self {
+                socket,
+                events,
+                open: false,
+                _on_open: on_open,
+                _on_message: on_message,
+                _on_error: on_error,
+                _on_close: on_close,
+            }
+        }
+
+        pub(crate) fn is_open(&self) -> bool {
+            self.open
+        }
+
+        pub(crate) fn send(&mut self, buffer: &[u8]) {
+            // Browsers throw on sends before the socket opens, so those are dropped like lost
+            // datagrams and ENet resends what it needs.
+            if let Some(socket) = &self.socket {
+                if socket.ready_state() == WebSocket::OPEN {
+                    _ = socket.send_with_u8_array(buffer);
+                }
+            }
+        }
+
+        pub(crate) fn receive(&mut self) -> Result<Option<Vec<u8>>, String> {
+            loop {
+                match self.events.borrow_mut().pop_front() {
+                    Some(WebSocketEvent::Open) => self.open = true,
+                    Some(WebSocketEvent::Message(data)) => return Ok(Some(data)),
+                    Some(WebSocketEvent::Error(message)) => {
+                        self.open = false;
+                        return Err(message);
+                    }
+                    None => return Ok(None),
+                }
+            }
+        }
+    }
+
+    impl Drop for WebSocketTransport {
+        fn drop(&mut self) {
+            if let Some(socket) = &self.socket {
+                socket.set_onopen(None);
+                socket.set_onmessage(None);
+                socket.set_onerror(None);
+                socket.set_onclose(None);
+                _ = socket.close();
+            }
+        }
+    }
+}
@@ -0,0 +1,3 @@
+use naia_server_socket::NaiaServerSocketError;
+
+pub type EnaiaError = enaia_shared::EnaiaError<NaiaServerSocketError>;
@@ -1,54 +1,71 @@
 use std::net::SocketAddr;
 
 use naia_server_socket::{NaiaServerSocketError, PacketReceiver, PacketSender, Socket};
-use naia_socket_shared::{LinkConditionerConfig, SocketConfig};
 
+mod error;
+mod multi;
+mod tls;
+mod websocket;
+
+pub use enaia_shared::{EnaiaConfig, LinkConditionerConfig, DEFAULT_MAX_DATAGRAM_SIZE};
+pub use error::*;
+pub use multi::*;
 pub use naia_server_socket::ServerAddrs;
+pub use tls::*;
+pub use websocket::*;
 
 pub struct EnaiaServer {
     packet_sender: Box<dyn PacketSender>,
     packet_receiver: Box<dyn PacketReceiver>,
+    max_datagram_size: usize,
 }
 
 impl EnaiaServer {
     pub fn new(server_address: ServerAddrs) -> Result<Self, NaiaServerSocketError> {
-        let (packet_sender, packet_receiver) = Socket::listen(
-            &server_address,
-            &SocketConfig::new(Some(LinkConditionerConfig::new(0, 0, 0.)), None),
-        );
+        Self::with_config(server_address, EnaiaConfig::default())
+    }
+
+    pub fn with_config(
+        server_address: ServerAddrs,
+        config: EnaiaConfig,
+    ) -> Result<Self, NaiaServerSocketError> {
+        let (packet_sender, packet_receiver) =
+            Socket::listen(&server_address, &config.socket_config());
         Ok(EnaiaServer {
             packet_sender,
             packet_receiver,
+            max_datagram_size: config.max_datagram_size,
         })
     }
 }
 
 impl rusty_enet::Socket for EnaiaServer {
     type PeerAddress = SocketAddr;
-    type Error = NaiaServerSocketError;
+    type Error = EnaiaError;
 
-    fn init(&mut self, _options: rusty_enet::SocketOptions) -> Result<(), NaiaServerSocketError> {
+    fn init(&mut self, _options: rusty_enet::SocketOptions) -> Result<(), EnaiaError> {
         Ok(())
     }
 
-    fn send(
-        &mut self,
-        address: Self::PeerAddress,
-        buffer: &[u8],
-    ) -> Result<usize, NaiaServerSocketError> {
+    /// Datagrams over `max_datagram_size` are dropped, like UDP drops what doesn't fit the path,
+    /// rather than failing the host and every other peer on it. ENet resends or times out the one
+    /// peer they were for.
+    fn send(&mut self, address: Self::PeerAddress, buffer: &[u8]) -> Result<usize, EnaiaError> {
+        if buffer.len() > self.max_datagram_size {
+            return Ok(buffer.len());
+        }
         self.packet_sender.send(&address, buffer)?;
         Ok(buffer.len())
     }
 
     fn receive(
         &mut self,
-        _mtu: usize,
-    ) -> Result<Option<(Self::PeerAddress, rusty_enet::PacketReceived)>, NaiaServerSocketError>
-    {
+        mtu: usize,
+    ) -> Result<Option<(Self::PeerAddress, rusty_enet::PacketReceived)>, EnaiaError> {
         match self.packet_receiver.receive() {
             Ok(Some((address, payload))) => Ok(Some((
                 address,
-                rusty_enet::PacketReceived::Complete(Vec::from(payload)),
+                enaia_shared::received(Vec::from(payload), mtu),
             ))),
             Ok(None) => Ok(None),
             Err(err) => Err(err.into()),
@@ -0,0 +1,113 @@
+use std::net::SocketAddr;
+
+use rusty_enet::Socket as _;
+
+use crate::{EnaiaError, EnaiaServer, EnaiaWebSocketServer};
+
+/// A peer of an [`EnaiaMultiServer`], by the transport it's on and its address there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EnaiaPeerAddress {
+    WebRtc(SocketAddr),
+    WebSocket(SocketAddr),
+}
+
+impl EnaiaPeerAddress {
+    pub fn socket_address(&self) -> SocketAddr {
+        match self {
+            Self::WebRtc(address) | Self::WebSocket(address) => *address,
+        }
+    }
+}
+
+impl rusty_enet::Address for EnaiaPeerAddress {
+    fn same_host(&self, other: &Self) -> bool {
+        self.socket_address().ip() == other.socket_address().ip()
+    }
+
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn is_broadcast(&self) -> bool {
+        false
+    }
+}
+
+/// Serves WebRTC peers and, optionally, WebSocket peers as one socket, so a single
+/// `rusty_enet::Host`, and everything kept per host like sessions, covers both transports.
+pub struct EnaiaMultiServer {
+    webrtc: EnaiaServer,
+    websocket: Option<EnaiaWebSocketServer>,
+    /// Which transport to receive from first next time, so neither can starve the other.
+    websocket_first: bool,
+}
+
+impl EnaiaMultiServer {
+    pub fn new(webrtc: EnaiaServer, websocket: Option<EnaiaWebSocketServer>) -> Self {
+        Self {
+            webrtc,
+            websocket,
+            websocket_first: false,
+        }
+    }
+
+    fn receive_websocket(
+        &mut self,
+        mtu: usize,
+    ) -> Result<Option<(EnaiaPeerAddress, rusty_enet::PacketReceived)>, EnaiaError> {
+        let Some(websocket) = &mut self.websocket else {
+            return Ok(None);
+        };
+        Ok(websocket
+            .receive(mtu)?
+            .map(|(address, packet)| (EnaiaPeerAddress::WebSocket(address), packet)))
+    }
+
+    fn receive_webrtc(
+        &mut self,
+        mtu: usize,
+    ) -> Result<Option<(EnaiaPeerAddress, rusty_enet::PacketReceived)>, EnaiaError> {
+        Ok(self
+            .webrtc
+            .receive(mtu)?
+            .map(|(address, packet)| (EnaiaPeerAddress::WebRtc(address), packet)))
+    }
+}
+
+impl rusty_enet::Socket for EnaiaMultiServer {
+    type PeerAddress = EnaiaPeerAddress;
+    type Error = EnaiaError;
+
+    /// Neither transport has anything to set up.
+    fn init(&mut self, _options: rusty_enet::SocketOptions) -> Result<(), EnaiaError> {
+        Ok(())
+    }
+
+    fn send(&mut self, address: Self::PeerAddress, buffer: &[u8]) -> Result<usize, EnaiaError> {
+        match address {
+            EnaiaPeerAddress::WebRtc(address) => self.webrtc.send(address, buffer),
+            EnaiaPeerAddress::WebSocket(address) => match &mut self.websocket {
+                Some(websocket) => websocket.send(address, buffer),
+                None => Ok(buffer.len()),
+            },
+        }
+    }
+
+    fn receive(
+        &mut self,
+        mtu: usize,
+    ) -> Result<Option<(Self::PeerAddress, rusty_enet::PacketReceived)>, EnaiaError> {
+        self.websocket_first = !self.websocket_first;
+        if self.websocket_first {
+            match self.receive_websocket(mtu)? {
+                Some(received) => Ok(Some(received)),
+                None => self.receive_webrtc(mtu),
+            }
+        } else {
+            match self.receive_webrtc(mtu)? {
+                Some(received) => Ok(Some(received)),
+                None => self.receive_websocket(mtu),
+            }
+        }
+    }
+}
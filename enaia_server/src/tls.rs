@@ -0,0 +1,213 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, ErrorKind, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use rustls::{
+    server::{ClientHello, ResolvesServerCert},
+    sign::{self, CertifiedKey},
+    Certificate, PrivateKey, ServerConfig, ServerConnection,
+};
+
+/// Terminates TLS in front of a plain TCP endpoint, such as the WebRTC session endpoint, so that
+/// browsers on HTTPS pages can reach it without a reverse proxy.
+///
+/// The session endpoint is where signaling happens, and is the only WebRTC endpoint that needs
+/// this. The data endpoint is UDP, and WebRTC already encrypts it with DTLS.
+///
+/// The certificate and key are read again whenever either file changes on disk, so renewed
+/// certificates take effect without a restart.
+pub struct TlsTerminator;
+
+impl TlsTerminator {
+    pub fn spawn(
+        address: SocketAddr,
+        backend: SocketAddr,
+        certificate: PathBuf,
+        key: PathBuf,
+    ) -> io::Result<()> {
+        let resolver = ReloadingCertificate::new(certificate, key)?;
+        let config = Arc::new(
+            ServerConfig::builder()
+                .with_safe_defaults()
+                .with_no_client_auth()
+                .with_cert_resolver(Arc::new(resolver)),
+        );
+        let backend = connectable_address(backend);
+        let listener = TcpListener::bind(address)?;
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(stream) = stream else {
+                    continue;
+                };
+                let config = config.clone();
+                std::thread::spawn(move || {
+                    _ = relay(stream, backend, config);
+                });
+            }
+        });
+        Ok(())
+    }
+}
+
+fn connectable_address(address: SocketAddr) -> SocketAddr {
+    match address {
+        SocketAddr::V4(address) if address.ip().is_unspecified() => {
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), address.port())
+        }
+        SocketAddr::V6(address) if address.ip().is_unspecified() => {
+            SocketAddr::new(Ipv6Addr::LOCALHOST.into(), address.port())
+        }
+        address => address,
+    }
+}
+
+fn relay(mut client: TcpStream, backend: SocketAddr, config: Arc<ServerConfig>) -> io::Result<()> {
+    let mut connection = ServerConnection::new(config).map_err(invalid_data)?;
+    client.set_read_timeout(Some(Duration::from_secs(10)))?;
+    while connection.is_handshaking() {
+        connection.complete_io(&mut client)?;
+    }
+    client.set_read_timeout(None)?;
+    let backend = TcpStream::connect(backend)?;
+    let connection = Mutex::new(connection);
+
+    // Each direction blocks on a thread of its own, and closes only its own half when it's done,
+    // so data still in flight the other way gets through.
+    std::thread::scope(|scope| {
+        let close = |result: io::Result<()>| {
+            if result.is_err() {
+                _ = client.shutdown(Shutdown::Both);
+                _ = backend.shutdown(Shutdown::Both);
+            }
+            result
+        };
+        let upload = scope.spawn(|| close(client_to_backend(&connection, &client, &backend)));
+        let download = close(backend_to_client(&connection, &client, &backend));
+        upload
+            .join()
+            .unwrap_or_else(|_| Err(ErrorKind::Other.into()))
+            .and(download)
+    })
+}
+
+fn client_to_backend(
+    connection: &Mutex<ServerConnection>,
+    mut client: &TcpStream,
+    mut backend: &TcpStream,
+) -> io::Result<()> {
+    let mut buffer = [0; 4096];
+    loop {
+        let received = client.read(&mut buffer)?;
+        let mut data = &buffer[0..received];
+        let mut plaintext = vec![];
+        let mut closed = received == 0;
+        {
+            let mut connection = connection.lock().unwrap();
+            while !data.is_empty() {
+                connection.read_tls(&mut data)?;
+                let state = connection.process_new_packets().map_err(invalid_data)?;
+                let start = plaintext.len();
+                plaintext.resize(start + state.plaintext_bytes_to_read(), 0);
+                connection.reader().read_exact(&mut plaintext[start..])?;
+                closed |= state.peer_has_closed();
+            }
+            while connection.wants_write() {
+                connection.write_tls(&mut client)?;
+            }
+        }
+        backend.write_all(&plaintext)?;
+        if closed {
+            return backend.shutdown(Shutdown::Write);
+        }
+    }
+}
+
+fn backend_to_client(
+    connection: &Mutex<ServerConnection>,
+    mut client: &TcpStream,
+    mut backend: &TcpStream,
+) -> io::Result<()> {
+    let mut buffer = [0; 4096];
+    loop {
+        let received = backend.read(&mut buffer)?;
+        let mut connection = connection.lock().unwrap();
+        if received == 0 {
+            connection.send_close_notify();
+        } else {
+            connection.writer().write_all(&buffer[0..received])?;
+        }
+        while connection.wants_write() {
+            connection.write_tls(&mut client)?;
+        }
+        if received == 0 {
+            return client.shutdown(Shutdown::Write);
+        }
+    }
+}
+
+fn invalid_data(err: impl std::error::Error + Send + Sync + 'static) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, err)
+}
+
+struct ReloadingCertificate {
+    certificate: PathBuf,
+    key: PathBuf,
+    loaded: Mutex<(Option<SystemTime>, Arc<CertifiedKey>)>,
+}
+
+impl ReloadingCertificate {
+    fn new(certificate: PathBuf, key: PathBuf) -> io::Result<Self> {
+        let modified = modified(&certificate, &key);
+        let certified_key = load_certified_key(&certificate, &key)?;
+        Ok(Self {
+            certificate,
+            key,
+            loaded: Mutex::new((modified, certified_key)),
+        })
+    }
+}
+
+impl ResolvesServerCert for ReloadingCertificate {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let mut loaded = self.loaded.lock().ok()?;
+        let modified = modified(&self.certificate, &self.key);
+        if modified != loaded.0 {
+            // A renewal may be caught halfway through writing the files, so keep serving the old
+            // certificate until the new one loads.
+            if let Ok(certified_key) = load_certified_key(&self.certificate, &self.key) {
+                *loaded = (modified, certified_key);
+            }
+        }
+        Some(loaded.1.clone())
+    }
+}
+
+fn modified(certificate: &Path, key: &Path) -> Option<SystemTime> {
+    let certificate = fs::metadata(certificate).and_then(|metadata| metadata.modified());
+    let key = fs::metadata(key).and_then(|metadata| metadata.modified());
+    Some(certificate.ok()?.max(key.ok()?))
+}
+
+fn load_certified_key(certificate: &Path, key: &Path) -> io::Result<Arc<CertifiedKey>> {
+    let certificates = rustls_pemfile::certs(&mut BufReader::new(File::open(certificate)?))?
+        .into_iter()
+        .map(Certificate)
+        .collect::<Vec<_>>();
+    let mut reader = BufReader::new(File::open(key)?);
+    let key = loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => break PrivateKey(key),
+            Some(_) => {}
+            None => return Err(io::Error::new(ErrorKind::InvalidData, "No private key.")),
+        }
+    };
+    let key = sign::any_supported_type(&key).map_err(invalid_data)?;
+    Ok(Arc::new(CertifiedKey::new(certificates, key)))
+}
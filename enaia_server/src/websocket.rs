@@ -0,0 +1,148 @@
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tungstenite::{Error, Message};
+
+use crate::{EnaiaConfig, EnaiaError};
+
+type Connections = Arc<Mutex<HashMap<SocketAddr, Sender<Vec<u8>>>>>;
+
+/// Serves ENet over WebSockets, one datagram per binary message, for clients whose network blocks
+/// the UDP traffic WebRTC needs. Each peer is known by the address of its TCP connection.
+///
+/// There's no TLS here; put a [`TlsTerminator`](crate::TlsTerminator) in front for `wss://`.
+pub struct EnaiaWebSocketServer {
+    incoming: Receiver<(SocketAddr, Vec<u8>)>,
+    connections: Connections,
+    max_datagram_size: usize,
+}
+
+impl EnaiaWebSocketServer {
+    pub fn new(address: SocketAddr) -> io::Result<Self> {
+        Self::with_config(address, EnaiaConfig::default())
+    }
+
+    /// Only `max_datagram_size` applies; TCP has no use for the link conditioner, the WebRTC
+    /// endpoint path or the client's timeouts.
+    pub fn with_config(address: SocketAddr, config: EnaiaConfig) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        let (incoming_sender, incoming) = mpsc::channel();
+        let connections = Connections::default();
+        {
+            let connections = connections.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming() {
+                    let Ok(stream) = stream else {
+                        continue;
+                    };
+                    let Ok(peer) = stream.peer_addr() else {
+                        continue;
+                    };
+                    let incoming = incoming_sender.clone();
+                    let connections = connections.clone();
+                    std::thread::spawn(move || {
+                        let (outgoing_sender, outgoing) = mpsc::channel();
+                        connections.lock().unwrap().insert(peer, outgoing_sender);
+                        _ = serve(stream, peer, outgoing, incoming);
+                        connections.lock().unwrap().remove(&peer);
+                    });
+                }
+            });
+        }
+        Ok(Self {
+            incoming,
+            connections,
+            max_datagram_size: config.max_datagram_size,
+        })
+    }
+}
+
+fn serve(
+    stream: TcpStream,
+    peer: SocketAddr,
+    outgoing: Receiver<Vec<u8>>,
+    incoming: Sender<(SocketAddr, Vec<u8>)>,
+) -> tungstenite::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let Ok(mut socket) = tungstenite::accept(stream) else {
+        return Ok(());
+    };
+    socket.get_mut().set_read_timeout(None)?;
+    socket.get_mut().set_nonblocking(true)?;
+    loop {
+        let mut idle = true;
+        loop {
+            match outgoing.try_recv() {
+                Ok(data) => {
+                    idle = false;
+                    ignore_would_block(socket.write(Message::Binary(data)))?;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            }
+        }
+        ignore_would_block(socket.flush())?;
+        match socket.read() {
+            Ok(Message::Binary(data)) => {
+                idle = false;
+                if incoming.send((peer, data)).is_err() {
+                    return Ok(());
+                }
+            }
+            Ok(_) => idle = false,
+            Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+        if idle {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+fn ignore_would_block(result: tungstenite::Result<()>) -> tungstenite::Result<()> {
+    match result {
+        Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => Ok(()),
+        result => result,
+    }
+}
+
+impl rusty_enet::Socket for EnaiaWebSocketServer {
+    type PeerAddress = SocketAddr;
+    type Error = EnaiaError;
+
+    fn init(&mut self, _options: rusty_enet::SocketOptions) -> Result<(), EnaiaError> {
+        Ok(())
+    }
+
+    /// Datagrams over `max_datagram_size` are dropped rather than failing the host, the same as
+    /// [`EnaiaServer`](crate::EnaiaServer) does.
+    fn send(&mut self, address: Self::PeerAddress, buffer: &[u8]) -> Result<usize, EnaiaError> {
+        if buffer.len() > self.max_datagram_size {
+            return Ok(buffer.len());
+        }
+        // Like a datagram to a closed port, anything for a connection that's gone is lost, and ENet
+        // times the peer out.
+        if let Some(connection) = self.connections.lock().unwrap().get(&address) {
+            _ = connection.send(buffer.to_vec());
+        }
+        Ok(buffer.len())
+    }
+
+    fn receive(
+        &mut self,
+        mtu: usize,
+    ) -> Result<Option<(Self::PeerAddress, rusty_enet::PacketReceived)>, EnaiaError> {
+        match self.incoming.try_recv() {
+            Ok((address, payload)) => Ok(Some((address, enaia_shared::received(payload, mtu)))),
+            Err(_) => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use naia_socket_shared::{LinkConditionerConfig, SocketConfig};
+
+/// ENet's default MTU, the largest datagram a host sends unless configured otherwise.
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1392;
+
+#[derive(Clone)]
+pub struct EnaiaConfig {
+    /// Simulated latency, jitter and loss applied to incoming packets. Off when unset.
+    pub link_conditioner: Option<LinkConditionerConfig>,
+    /// Path of the WebRTC session endpoint, naia's default when unset.
+    pub rtc_endpoint_path: Option<String>,
+    /// How long signaling and ICE, or the WebSocket handshake, may take before the connection
+    /// counts as failed. Client only.
+    pub signaling_timeout: Duration,
+    /// How long a connected transport may go without delivering a packet before it counts as
+    /// lost. ENet pings twice a second, so an idle but healthy session never gets close. Client
+    /// only.
+    pub transport_timeout: Duration,
+    /// Largest datagram sent over the data channel, so ENet's MTU must not exceed it. Larger
+    /// sends fail with [`EnaiaError::PacketTooLarge`](crate::EnaiaError::PacketTooLarge) on the
+    /// client, and are dropped on the server so one peer can't fail the host.
+    pub max_datagram_size: usize,
+}
+
+impl Default for EnaiaConfig {
+    fn default() -> Self {
+        Self {
+            link_conditioner: None,
+            rtc_endpoint_path: None,
+            signaling_timeout: Duration::from_secs(10),
+            transport_timeout: Duration::from_secs(5),
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+        }
+    }
+}
+
+impl EnaiaConfig {
+    pub fn good_network() -> Self {
+        Self {
+            link_conditioner: Some(LinkConditionerConfig::good_condition()),
+            ..Default::default()
+        }
+    }
+
+    pub fn average_network() -> Self {
+        Self {
+            link_conditioner: Some(LinkConditionerConfig::average_condition()),
+            ..Default::default()
+        }
+    }
+
+    pub fn poor_network() -> Self {
+        Self {
+            link_conditioner: Some(LinkConditionerConfig::poor_condition()),
+            ..Default::default()
+        }
+    }
+
+    pub fn socket_config(&self) -> SocketConfig {
+        SocketConfig::new(
+            self.link_conditioner.clone(),
+            self.rtc_endpoint_path.clone(),
+        )
+    }
+}
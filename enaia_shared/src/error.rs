@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// An error from a transport, where `E` is the naia socket's own error type.
+#[derive(Debug)]
+pub enum EnaiaError<E> {
+    Socket(E),
+    /// The WebSocket transport failed to connect or was closed.
+    WebSocket(String),
+    /// The datagram is larger than the data channel carries, so naia would drop it.
+    PacketTooLarge {
+        size: usize,
+        max: usize,
+    },
+}
+
+impl<E: fmt::Display> fmt::Display for EnaiaError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Socket(err) => err.fmt(f),
+            Self::WebSocket(message) => message.fmt(f),
+            Self::PacketTooLarge { size, max } => write!(
+                f,
+                "Packet of {} bytes exceeds the {} byte datagram limit.",
+                size, max
+            ),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for EnaiaError<E> {}
+
+impl<E> From<E> for EnaiaError<E> {
+    fn from(err: E) -> Self {
+        Self::Socket(err)
+    }
+}
@@ -0,0 +1,18 @@
+//! What `enaia_client` and `enaia_server` have in common.
+
+mod config;
+mod error;
+
+pub use config::*;
+pub use error::*;
+pub use naia_socket_shared::LinkConditionerConfig;
+
+/// Hands a datagram to ENet. Like a UDP socket with a buffer of `mtu` bytes, anything larger is
+/// reported as truncated rather than handed to ENet whole.
+pub fn received(payload: Vec<u8>, mtu: usize) -> rusty_enet::PacketReceived {
+    if payload.len() > mtu {
+        rusty_enet::PacketReceived::Partial
+    } else {
+        rusty_enet::PacketReceived::Complete(payload)
+    }
+}
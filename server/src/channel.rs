@@ -1,68 +1,295 @@
 use std::{
     net::SocketAddr,
     str,
-    sync::mpsc::{self, RecvTimeoutError},
-    time::Duration,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use rusty_enet::Packet;
-use serde::{Deserialize, Serialize};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
 
-use crate::{EchoChannelStream, TcpChannelStream, UdpChannelStream};
+use crate::{
+    ChannelRegistry, ChargenChannelStream, ChargenConfig, DiscardChannelStream, EchoChannelStream,
+    EchoConfig, HttpChannelStream, HttpRequest, ServerConfig, ServiceProtocol, ServiceRegistry,
+    SinkChannelStream, SinkConfig, SocketTarget, TcpChannelStream, TlsChannelStream,
+    UdpChannelStream, WebSocketChannelStream,
+};
 
+// Derived as inherent functions, which the trait impls below wrap.
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub enum ChannelConfig {
-    Echo,
-    Tcp(SocketAddr),
-    Udp(SocketAddr),
+    /// Also takes the bare `"Echo"` it was before it had options.
+    Echo(EchoConfig),
+    Discard,
+    Chargen(ChargenConfig),
+    Sink(SinkConfig),
+    /// Also takes a bare address, without options.
+    Tcp(SocketTarget),
+    Udp(SocketTarget),
+    WebSocket {
+        url: String,
+        #[serde(default)]
+        headers: Vec<(String, String)>,
+        #[serde(default)]
+        subprotocols: Vec<String>,
+    },
+    Http {
+        method: String,
+        url: String,
+        #[serde(default)]
+        headers: Vec<(String, String)>,
+        #[serde(default)]
+        max_redirects: Option<u32>,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+    Tls {
+        address: String,
+        #[serde(default)]
+        sni: Option<String>,
+        #[serde(default)]
+        alpn: Vec<String>,
+        #[serde(default = "default_verify")]
+        verify: bool,
+    },
+    /// A backend of a service configured on the server, see [`ServiceRegistry`].
+    Service(String),
+    /// A channel kind registered with a [`ChannelRegistry`].
+    Custom {
+        name: String,
+        #[serde(default)]
+        config: Value,
+    },
+}
+
+impl ChannelConfig {
+    /// Whether the channel connects to an address the client chose.
+    pub fn has_destination(&self) -> bool {
+        matches!(
+            self,
+            Self::Tcp(_)
+                | Self::Udp(_)
+                | Self::WebSocket { .. }
+                | Self::Http { .. }
+                | Self::Tls { .. }
+        )
+    }
+}
+
+impl Serialize for ChannelConfig {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ChannelConfig::serialize(self, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChannelConfig {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::String(name) if name == "Echo" => Ok(Self::Echo(EchoConfig::default())),
+            value => ChannelConfig::deserialize(value).map_err(D::Error::custom),
+        }
+    }
+}
+
+fn default_verify() -> bool {
+    true
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ChannelStatus {
     Connecting,
     Connected,
+    /// The target won't send anything more, but still takes data, like a TCP socket that read
+    /// EOF. Streams are still asked to receive, to notice errors.
+    Eof,
     Disconnected,
 }
 
+pub enum ChannelEvent {
+    /// The target is connected. Sent once, before any data.
+    Connected,
+    Data(Packet),
+    /// Nothing more will be sent in this direction.
+    Shutdown,
+}
+
 pub struct Channel {
-    sender: mpsc::Sender<Packet>,
-    receiver: mpsc::Receiver<Packet>,
+    /// None once the channel is closing.
+    sender: Option<mpsc::Sender<ChannelEvent>>,
+    receiver: mpsc::Receiver<ChannelEvent>,
+    opened: Instant,
+    bytes_sent: u64,
+    bytes_received: u64,
+    packets_sent: u64,
+    packets_received: u64,
 }
 
 impl Channel {
-    pub fn new(config: ChannelConfig) -> Self {
-        let (sender, channel_receiver) = mpsc::channel::<Packet>();
-        let (channel_sender, receiver) = mpsc::channel::<Packet>();
+    /// `client_address` is where the client connected from, for targets that are sent a PROXY
+    /// protocol header.
+    pub fn new(
+        config: ChannelConfig,
+        server_config: Arc<ServerConfig>,
+        registry: Arc<ChannelRegistry>,
+        services: ServiceRegistry,
+        client_address: Option<SocketAddr>,
+    ) -> Self {
+        let (sender, channel_receiver) = mpsc::channel::<ChannelEvent>();
+        let (channel_sender, receiver) = mpsc::channel::<ChannelEvent>();
         std::thread::spawn(move || {
             let Ok(mut channel) = || -> Result<Box<dyn ChannelStream>> {
+                if server_config.policy.services_only && config.has_destination() {
+                    bail!("Only services are allowed.");
+                }
+                let header = |address: SocketAddr, datagram| {
+                    server_config
+                        .policy
+                        .proxy_protocol(&address.ip().to_string(), address.port())
+                        .map(|version| version.header(client_address, address, datagram))
+                };
+                let eof_idle_timeout = server_config
+                    .tcp
+                    .eof_idle_timeout_ms
+                    .map(Duration::from_millis);
                 Ok(match config {
-                    ChannelConfig::Echo => Box::new(EchoChannelStream::new()),
-                    ChannelConfig::Tcp(address) => Box::new(TcpChannelStream::new(address)?),
-                    ChannelConfig::Udp(address) => Box::new(UdpChannelStream::new(address)?),
+                    ChannelConfig::Echo(config) => {
+                        Box::new(EchoChannelStream::new(config.clamp(&server_config.echo)))
+                    }
+                    ChannelConfig::Discard => Box::new(DiscardChannelStream::new()),
+                    ChannelConfig::Chargen(config) => Box::new(ChargenChannelStream::new(
+                        config.clamp(&server_config.chargen),
+                    )),
+                    ChannelConfig::Sink(config) => {
+                        Box::new(SinkChannelStream::new(config.clamp(&server_config.sink)))
+                    }
+                    ChannelConfig::Tcp(SocketTarget { address, options }) => {
+                        let options = options.clamp(&server_config.socket_limits);
+                        let addresses = server_config.policy.resolve_address(&address)?;
+                        let mut stream = Err(anyhow!("Host {} has no addresses.", address));
+                        for address in addresses {
+                            stream = TcpChannelStream::new(
+                                address,
+                                header(address, false).as_deref(),
+                                &options,
+                                None,
+                                eof_idle_timeout,
+                            );
+                            if stream.is_ok() {
+                                break;
+                            }
+                        }
+                        Box::new(stream?)
+                    }
+                    ChannelConfig::Udp(SocketTarget { address, options }) => {
+                        // Nothing tells whether a UDP address works, so only the first is used.
+                        let address = server_config.policy.resolve_address(&address)?[0];
+                        Box::new(UdpChannelStream::new(
+                            address,
+                            header(address, true),
+                            &options.clamp(&server_config.socket_limits),
+                        )?)
+                    }
+                    ChannelConfig::WebSocket {
+                        url,
+                        headers,
+                        subprotocols,
+                    } => Box::new(WebSocketChannelStream::new(
+                        &url,
+                        &headers,
+                        &subprotocols,
+                        &server_config.policy,
+                        Duration::from_millis(server_config.tls.timeout_ms),
+                    )?),
+                    ChannelConfig::Http {
+                        method,
+                        url,
+                        headers,
+                        max_redirects,
+                        timeout_ms,
+                    } => Box::new(HttpChannelStream::new(
+                        HttpRequest {
+                            method,
+                            url,
+                            headers,
+                            max_redirects,
+                            timeout_ms,
+                        },
+                        server_config.policy.clone(),
+                        server_config.http.clone(),
+                    )?),
+                    ChannelConfig::Tls {
+                        address,
+                        sni,
+                        alpn,
+                        verify,
+                    } => Box::new(TlsChannelStream::new(
+                        &address,
+                        sni.as_deref(),
+                        &alpn,
+                        verify,
+                        &server_config.tls,
+                        &server_config.policy,
+                        client_address,
+                    )?),
+                    // Services are configured by the operator, so the destination policy doesn't
+                    // apply to their backends.
+                    ChannelConfig::Service(name) => {
+                        services.open(&name, |protocol, address, options, timeout| {
+                            Ok(match protocol {
+                                ServiceProtocol::Tcp => Box::new(TcpChannelStream::new(
+                                    address,
+                                    header(address, false).as_deref(),
+                                    options,
+                                    Some(timeout),
+                                    eof_idle_timeout,
+                                )?),
+                                ServiceProtocol::Udp => Box::new(UdpChannelStream::new(
+                                    address,
+                                    header(address, true),
+                                    options,
+                                )?),
+                            })
+                        })?
+                    }
+                    ChannelConfig::Custom { name, config } => registry.open(&name, config)?,
                 })
             }() else {
                 return;
             };
             let mut connected = false;
+            let mut eof = false;
             loop {
-                if let Err(_) = || -> Result<()> {
+                if (|| -> Result<()> {
                     match channel.status()? {
                         ChannelStatus::Connecting => {}
-                        ChannelStatus::Connected => {
+                        status @ (ChannelStatus::Connected | ChannelStatus::Eof) => {
                             if !connected {
-                                sender.send(Packet::reliable(&[]))?;
+                                sender.send(ChannelEvent::Connected)?;
                                 connected = true;
                             }
-                            match receiver.recv_timeout(Duration::ZERO) {
-                                Ok(packet) => {
-                                    channel.send(packet)?;
+                            loop {
+                                match receiver.recv_timeout(Duration::ZERO) {
+                                    Ok(ChannelEvent::Data(packet)) => {
+                                        channel.send(packet)?;
+                                    }
+                                    Ok(ChannelEvent::Shutdown) => channel.shutdown()?,
+                                    Err(RecvTimeoutError::Timeout) => break,
+                                    Err(_) => bail!("Disconnected"),
                                 }
-                                Err(RecvTimeoutError::Timeout) => {}
-                                Err(_) => bail!("Disconnected"),
                             }
+                            // Still read after EOF, since that's where a reset shows up.
                             while let Some(data) = channel.receive()? {
-                                sender.send(data)?;
+                                sender.send(ChannelEvent::Data(data))?;
+                            }
+                            if status == ChannelStatus::Eof && !eof {
+                                sender.send(ChannelEvent::Shutdown)?;
+                                eof = true;
                             }
                         }
                         ChannelStatus::Disconnected => {
@@ -71,33 +298,149 @@ impl Channel {
                     }
                     std::thread::sleep(Duration::from_millis(10));
                     Ok(())
-                }() {
+                })()
+                .is_err()
+                {
                     break;
                 }
             }
+            // The proxy takes the sender going away to mean the target is closed, so it has to be.
+            drop(channel);
+            drop(sender);
         });
         Channel {
-            sender: channel_sender,
+            sender: Some(channel_sender),
             receiver: channel_receiver,
+            opened: Instant::now(),
+            bytes_sent: 0,
+            bytes_received: 0,
+            packets_sent: 0,
+            packets_received: 0,
         }
     }
 
-    pub fn send(&mut self, packet: Packet) -> Result<()> {
-        self.sender.send(packet)?;
+    pub fn send(&mut self, event: ChannelEvent) -> Result<()> {
+        if let ChannelEvent::Data(packet) = &event {
+            self.bytes_received += packet.data().len() as u64;
+            self.packets_received += 1;
+        }
+        self.sender
+            .as_ref()
+            .ok_or_else(|| anyhow!("Channel closing."))?
+            .send(event)?;
         Ok(())
     }
 
-    pub fn receive(&mut self) -> Result<Option<Packet>> {
+    /// Stops the worker, which closes the target. [`receive`](Self::receive) fails once it has,
+    /// and events it still gets before that are stale.
+    pub fn close(&mut self) {
+        self.sender = None;
+    }
+
+    pub fn closing(&self) -> bool {
+        self.sender.is_none()
+    }
+
+    pub fn receive(&mut self) -> Result<Option<ChannelEvent>> {
         match self.receiver.recv_timeout(Duration::ZERO) {
-            Ok(packet) => Ok(Some(packet)),
+            Ok(event) => {
+                if let ChannelEvent::Data(packet) = &event {
+                    self.bytes_sent += packet.data().len() as u64;
+                    self.packets_sent += 1;
+                }
+                Ok(Some(event))
+            }
             Err(RecvTimeoutError::Timeout) => Ok(None),
             Err(err) => Err(err.into()),
         }
     }
+
+    /// Counts from the server's side, so bytes sent went to the client. The round-trip time and
+    /// packet loss are left for the caller to fill in from the peer.
+    pub fn stats(&self) -> ChannelStats {
+        ChannelStats {
+            bytes_sent: self.bytes_sent,
+            bytes_received: self.bytes_received,
+            packets_sent: self.packets_sent,
+            packets_received: self.packets_received,
+            connected_ms: self.opened.elapsed().as_millis() as u64,
+            ..Default::default()
+        }
+    }
+}
+
+/// A channel's numbers as the server sees them, sent to clients that ask with a stats frame.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChannelStats {
+    pub round_trip_time_ms: u64,
+    pub round_trip_time_variance_ms: u64,
+    /// Fraction of reliable packets to the client that had to be resent, from 0 to 1.
+    pub packet_loss: f32,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub packets_sent: u64,
+    pub packets_received: u64,
+    pub connected_ms: u64,
 }
 
 pub trait ChannelStream {
     fn status(&mut self) -> Result<ChannelStatus>;
     fn send(&mut self, data: Packet) -> Result<()>;
     fn receive(&mut self) -> Result<Option<Packet>>;
+
+    /// The client won't send anything more. Streams without a notion of half-close ignore it.
+    fn shutdown(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> serde_json::Result<ChannelConfig> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn bare_echo_is_echo_with_defaults() {
+        let ChannelConfig::Echo(config) = parse(r#""Echo""#).unwrap() else {
+            panic!("not an Echo channel");
+        };
+        assert_eq!(config.latency_ms, 0);
+        assert_eq!(config.lifetime_ms, None);
+    }
+
+    #[test]
+    fn other_configs_deserialize_as_derived() {
+        let ChannelConfig::Echo(config) = parse(r#"{"Echo": {"latency_ms": 50}}"#).unwrap() else {
+            panic!("not an Echo channel");
+        };
+        assert_eq!(config.latency_ms, 50);
+        assert!(matches!(
+            parse(r#""Discard""#).unwrap(),
+            ChannelConfig::Discard
+        ));
+        let ChannelConfig::Tcp(target) = parse(r#"{"Tcp": "1.2.3.4:80"}"#).unwrap() else {
+            panic!("not a Tcp channel");
+        };
+        assert_eq!(target.address, "1.2.3.4:80");
+        assert!(matches!(
+            parse(r#"{"Service": "game-eu"}"#).unwrap(),
+            ChannelConfig::Service(name) if name == "game-eu"
+        ));
+        assert!(parse(r#""Unknown""#).is_err());
+        assert!(parse(r#"{"Echo": 1}"#).is_err());
+    }
+
+    #[test]
+    fn serializes_as_derived() {
+        let json = serde_json::to_string(&ChannelConfig::Echo(EchoConfig::default())).unwrap();
+        assert!(json.starts_with(r#"{"Echo":{"#));
+        assert!(matches!(parse(&json).unwrap(), ChannelConfig::Echo(_)));
+        assert_eq!(
+            serde_json::to_string(&ChannelConfig::Discard).unwrap(),
+            r#""Discard""#
+        );
+    }
 }
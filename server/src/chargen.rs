@@ -0,0 +1,114 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use rusty_enet::Packet;
+use serde::{Deserialize, Serialize};
+
+use crate::{ChannelStatus, ChannelStream};
+
+pub const CHARGEN_MAX_PACKET_SIZE: usize = 65536;
+/// Most that's generated in one go, in seconds at the configured rate.
+const CHARGEN_MAX_BURST_SECS: f64 = 0.1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChargenConfig {
+    /// Bytes generated per second.
+    pub rate: u64,
+    /// Bytes per packet, up to `CHARGEN_MAX_PACKET_SIZE`.
+    pub size: usize,
+    /// Send packets unreliably, to measure loss instead of reliable throughput.
+    pub unreliable: bool,
+}
+
+impl Default for ChargenConfig {
+    fn default() -> Self {
+        Self {
+            rate: 64 * 1024,
+            size: 1024,
+            unreliable: false,
+        }
+    }
+}
+
+/// Bounds on the [`ChargenConfig`] clients ask for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChargenLimits {
+    pub max_rate: u64,
+    /// Up to `CHARGEN_MAX_PACKET_SIZE`.
+    pub max_size: usize,
+}
+
+impl Default for ChargenLimits {
+    fn default() -> Self {
+        Self {
+            max_rate: 4 * 1024 * 1024,
+            max_size: 16 * 1024,
+        }
+    }
+}
+
+impl ChargenConfig {
+    pub fn clamp(&self, limits: &ChargenLimits) -> Self {
+        Self {
+            rate: self.rate.min(limits.max_rate),
+            size: self
+                .size
+                .clamp(1, limits.max_size.clamp(1, CHARGEN_MAX_PACKET_SIZE)),
+            unreliable: self.unreliable,
+        }
+    }
+}
+
+/// Generates the rotating printable ASCII pattern of RFC 864 at a fixed rate.
+pub struct ChargenChannelStream {
+    config: ChargenConfig,
+    instant: Instant,
+    generated: u64,
+    offset: usize,
+}
+
+impl ChargenChannelStream {
+    /// `config` should already be clamped to the server's [`ChargenLimits`].
+    pub fn new(config: ChargenConfig) -> Self {
+        Self {
+            config,
+            instant: Instant::now(),
+            generated: 0,
+            offset: 0,
+        }
+    }
+}
+
+impl ChannelStream for ChargenChannelStream {
+    fn status(&mut self) -> Result<ChannelStatus> {
+        Ok(ChannelStatus::Connected)
+    }
+
+    fn send(&mut self, _packet: Packet) -> Result<()> {
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Packet>> {
+        let allowed = (self.instant.elapsed().as_secs_f64() * self.config.rate as f64) as u64;
+        // A channel that fell behind, say while its thread was descheduled, skips what it missed
+        // rather than catching up in one burst.
+        let burst = ((self.config.rate as f64 * CHARGEN_MAX_BURST_SECS) as u64)
+            .max(self.config.size as u64);
+        self.generated = self.generated.max(allowed.saturating_sub(burst));
+        if self.generated + self.config.size as u64 > allowed {
+            return Ok(None);
+        }
+        self.generated += self.config.size as u64;
+        let data = (0..self.config.size)
+            .map(|index| b' ' + ((self.offset + index) % 95) as u8)
+            .collect::<Vec<_>>();
+        self.offset = (self.offset + self.config.size) % 95;
+        if self.config.unreliable {
+            Ok(Some(Packet::unreliable(&data)))
+        } else {
+            Ok(Some(Packet::reliable(&data)))
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    policy::host_matches, ChargenLimits, DestinationPolicy, EchoLimits, ServiceConfig, SinkLimits,
+    SocketLimits,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub session_address: SocketAddr,
+    pub webrtc_address: SocketAddr,
+    /// URL clients are told to send WebRTC data to, e.g. `https://example.com:14192`.
+    pub public_webrtc_url: String,
+    /// Where to accept ENet over WebSockets, for clients whose network blocks WebRTC, e.g.
+    /// `0.0.0.0:14196`. Disabled when unset.
+    pub websocket_address: Option<SocketAddr>,
+    /// Serve the session endpoint over HTTPS as well, without a reverse proxy in front.
+    pub https: Option<HttpsSettings>,
+    pub policy: DestinationPolicy,
+    pub http: HttpLimits,
+    pub tcp: TcpSettings,
+    pub tls: TlsSettings,
+    pub echo: EchoLimits,
+    pub chargen: ChargenLimits,
+    pub sink: SinkLimits,
+    pub resumption: ResumptionSettings,
+    /// Bounds on the socket options clients ask for on TCP and UDP channels.
+    pub socket_limits: SocketLimits,
+    /// Backend pools clients open by name with `{"Service": name}`. Reloaded when the config file
+    /// changes.
+    pub services: HashMap<String, ServiceConfig>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            session_address: "0.0.0.0:14191".parse().unwrap(),
+            webrtc_address: "0.0.0.0:14192".parse().unwrap(),
+            public_webrtc_url: "http://127.0.0.1:14192".to_owned(),
+            websocket_address: None,
+            https: None,
+            policy: DestinationPolicy::default(),
+            http: HttpLimits::default(),
+            tcp: TcpSettings::default(),
+            tls: TlsSettings::default(),
+            echo: EchoLimits::default(),
+            chargen: ChargenLimits::default(),
+            sink: SinkLimits::default(),
+            resumption: ResumptionSettings::default(),
+            socket_limits: SocketLimits::default(),
+            services: HashMap::new(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpsSettings {
+    pub address: SocketAddr,
+    /// Where to accept `wss://` connections for the WebSocket transport, if it's enabled.
+    #[serde(default)]
+    pub websocket_address: Option<SocketAddr>,
+    /// PEM certificate chain and private key, e.g. certbot's `fullchain.pem` and `privkey.pem`.
+    /// Both are reloaded when they change on disk.
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpLimits {
+    pub max_redirects: u32,
+    pub timeout_ms: u64,
+    pub max_request_bytes: u64,
+    pub max_response_bytes: u64,
+}
+
+impl Default for HttpLimits {
+    fn default() -> Self {
+        Self {
+            max_redirects: 5,
+            timeout_ms: 30_000,
+            max_request_bytes: 16 * 1024 * 1024,
+            max_response_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TcpSettings {
+    /// Close TCP channels whose target reached EOF once the client sent nothing for this long,
+    /// for clients that never shut down their side. Unset keeps them half-open until the client
+    /// shuts down or closes, however long it takes.
+    pub eof_idle_timeout_ms: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsSettings {
+    /// Trust the operating system's root certificates. When disabled and `ca_files` is empty, the
+    /// bundled Mozilla roots are used instead.
+    pub system_roots: bool,
+    /// PEM files with additional root certificates to trust.
+    pub ca_files: Vec<PathBuf>,
+    /// Client certificates presented to targets that ask for one. The first whose `hosts` match
+    /// the target applies, and other targets get none.
+    pub client_certificates: Vec<ClientCertificate>,
+    /// Let clients turn off certificate verification with `verify: false`.
+    pub allow_insecure: bool,
+    /// How long connecting to a TLS or WebSocket target and the handshake may take.
+    pub timeout_ms: u64,
+}
+
+impl Default for TlsSettings {
+    fn default() -> Self {
+        Self {
+            system_roots: true,
+            ca_files: vec![],
+            client_certificates: vec![],
+            allow_insecure: false,
+            timeout_ms: 10_000,
+        }
+    }
+}
+
+impl TlsSettings {
+    /// The client certificate to present to `host`, if any.
+    pub fn client_certificate(&self, host: &str) -> Option<&ClientCertificate> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        self.client_certificates.iter().find(|certificate| {
+            certificate
+                .hosts
+                .iter()
+                .any(|pattern| host_matches(pattern, host))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientCertificate {
+    /// Hosts the certificate is presented to, matched like `policy.allowed_hosts`.
+    pub hosts: Vec<String>,
+    /// PEM certificate chain and private key.
+    pub certificate: PathBuf,
+    pub key: PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResumptionSettings {
+    /// How long the channels of a client that timed out are kept open for it to reconnect. Zero
+    /// closes them right away.
+    pub grace_period_ms: u64,
+    /// Most data kept per tunnel until the client acknowledges it, to send again after a resume.
+    /// A tunnel that needs more can't be resumed anymore.
+    pub max_buffered_bytes: usize,
+}
+
+impl Default for ResumptionSettings {
+    fn default() -> Self {
+        Self {
+            grace_period_ms: 30_000,
+            max_buffered_bytes: 4 * 1024 * 1024,
+        }
+    }
+}
@@ -3,19 +3,64 @@ use std::{
     time::{Duration, Instant},
 };
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use rand::Rng;
 use rusty_enet::Packet;
+use serde::{Deserialize, Serialize};
 
 use crate::{ChannelStatus, ChannelStream};
 
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EchoConfig {
+    /// Disconnect after this long. The channel stays open until the client leaves when unset.
+    pub lifetime_ms: Option<u64>,
+    /// Delay added to every packet before it's echoed back.
+    pub latency_ms: u64,
+    /// Random extra delay of up to this much, added on top of `latency_ms`.
+    pub jitter_ms: u64,
+    /// Fraction of packets dropped instead of echoed, from `0.0` to `1.0`.
+    pub loss: f32,
+}
+
+/// Bounds on the [`EchoConfig`] clients ask for, so queued packets can't be held for days.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EchoLimits {
+    pub max_latency_ms: u64,
+    pub max_jitter_ms: u64,
+}
+
+impl Default for EchoLimits {
+    fn default() -> Self {
+        Self {
+            max_latency_ms: 10_000,
+            max_jitter_ms: 10_000,
+        }
+    }
+}
+
+impl EchoConfig {
+    pub fn clamp(&self, limits: &EchoLimits) -> Self {
+        Self {
+            lifetime_ms: self.lifetime_ms,
+            latency_ms: self.latency_ms.min(limits.max_latency_ms),
+            jitter_ms: self.jitter_ms.min(limits.max_jitter_ms),
+            loss: self.loss,
+        }
+    }
+}
+
 pub struct EchoChannelStream {
+    config: EchoConfig,
     instant: Instant,
-    packets: VecDeque<Packet>,
+    packets: VecDeque<(Instant, Packet)>,
 }
 
 impl EchoChannelStream {
-    pub fn new() -> Self {
+    pub fn new(config: EchoConfig) -> Self {
         Self {
+            config,
             instant: Instant::now(),
             packets: VecDeque::new(),
         }
@@ -24,19 +69,40 @@ impl EchoChannelStream {
 
 impl ChannelStream for EchoChannelStream {
     fn status(&mut self) -> Result<ChannelStatus> {
-        if self.instant.elapsed() > Duration::from_secs(3) {
-            Ok(ChannelStatus::Disconnected)
-        } else {
-            Ok(ChannelStatus::Connected)
+        match self.config.lifetime_ms {
+            Some(lifetime_ms) if self.instant.elapsed() > Duration::from_millis(lifetime_ms) => {
+                Ok(ChannelStatus::Disconnected)
+            }
+            _ => Ok(ChannelStatus::Connected),
         }
     }
 
     fn send(&mut self, packet: Packet) -> Result<()> {
-        self.packets.push_back(packet);
+        let mut rng = rand::thread_rng();
+        if self.config.loss > 0. && rng.gen::<f32>() < self.config.loss {
+            return Ok(());
+        }
+        let mut delay = self.config.latency_ms;
+        if self.config.jitter_ms > 0 {
+            delay = delay.saturating_add(rng.gen_range(0..=self.config.jitter_ms));
+        }
+        let Some(release) = Instant::now().checked_add(Duration::from_millis(delay)) else {
+            bail!("Delay of {} ms is too long.", delay);
+        };
+        // Jitter may release a packet before ones queued ahead of it, the same as a real network.
+        let index = self
+            .packets
+            .partition_point(|(queued_release, _)| *queued_release <= release);
+        self.packets.insert(index, (release, packet));
         Ok(())
     }
 
     fn receive(&mut self) -> Result<Option<Packet>> {
-        Ok(self.packets.pop_front())
+        match self.packets.front() {
+            Some((release, _)) if *release <= Instant::now() => {
+                Ok(self.packets.pop_front().map(|(_, packet)| packet))
+            }
+            _ => Ok(None),
+        }
     }
 }
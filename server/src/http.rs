@@ -0,0 +1,237 @@
+use std::{
+    io::{self, ErrorKind, Read},
+    net::SocketAddr,
+    sync::mpsc::{self, TryRecvError},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use rusty_enet::Packet;
+use serde_json::json;
+use ureq::AgentBuilder;
+
+use crate::{ChannelStatus, ChannelStream, DestinationPolicy, HttpLimits};
+
+/// Client to server: a chunk of the request body. Server to client: a chunk of the response body.
+pub const HTTP_BODY: u8 = 0;
+/// Client to server: the request body is complete. Server to client: the response is complete.
+pub const HTTP_END: u8 = 1;
+/// Server to client: the response status line and headers, as JSON.
+pub const HTTP_HEAD: u8 = 2;
+/// Server to client: the request failed, followed by a UTF-8 message.
+pub const HTTP_ERROR: u8 = 3;
+
+pub struct HttpRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub max_redirects: Option<u32>,
+    pub timeout_ms: Option<u64>,
+}
+
+pub struct HttpChannelStream {
+    body: Option<mpsc::Sender<Vec<u8>>>,
+    receiver: mpsc::Receiver<Vec<u8>>,
+    done: bool,
+}
+
+impl HttpChannelStream {
+    pub fn new(
+        request: HttpRequest,
+        policy: DestinationPolicy,
+        limits: HttpLimits,
+    ) -> Result<Self> {
+        match request.method.to_ascii_uppercase().as_str() {
+            "GET" | "HEAD" | "POST" | "PUT" | "PATCH" | "DELETE" | "OPTIONS" => {}
+            method => bail!("Unsupported HTTP method {}.", method),
+        }
+        if !request.url.starts_with("http://") && !request.url.starts_with("https://") {
+            bail!("Unsupported URL {}.", request.url);
+        }
+        let (body_sender, body_receiver) = mpsc::channel::<Vec<u8>>();
+        let (sender, receiver) = mpsc::channel::<Vec<u8>>();
+        std::thread::spawn(move || {
+            if let Err(err) = perform(request, policy, limits, body_receiver, &sender) {
+                let mut data = vec![HTTP_ERROR];
+                data.extend(err.to_string().as_bytes());
+                _ = sender.send(data);
+            }
+        });
+        Ok(Self {
+            body: Some(body_sender),
+            receiver,
+            done: false,
+        })
+    }
+}
+
+impl ChannelStream for HttpChannelStream {
+    fn status(&mut self) -> Result<ChannelStatus> {
+        if self.done {
+            Ok(ChannelStatus::Disconnected)
+        } else {
+            Ok(ChannelStatus::Connected)
+        }
+    }
+
+    fn send(&mut self, packet: Packet) -> Result<()> {
+        match packet.data().split_first() {
+            Some((&HTTP_BODY, data)) => {
+                if let Some(body) = &self.body {
+                    // The request may already have finished (or failed), in which case the rest of
+                    // the body is no longer needed.
+                    _ = body.send(data.to_vec());
+                }
+                Ok(())
+            }
+            Some((&HTTP_END, _)) => {
+                self.body = None;
+                Ok(())
+            }
+            _ => bail!("Invalid HTTP message."),
+        }
+    }
+
+    fn receive(&mut self) -> Result<Option<Packet>> {
+        match self.receiver.try_recv() {
+            Ok(data) => Ok(Some(Packet::reliable(&data))),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                self.done = true;
+                Ok(None)
+            }
+        }
+    }
+}
+
+struct BodyReader {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    buffer: Vec<u8>,
+    position: usize,
+    remaining: u64,
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.buffer.len() {
+            match self.receiver.recv() {
+                Ok(data) => {
+                    if data.len() as u64 > self.remaining {
+                        return Err(io::Error::new(
+                            ErrorKind::InvalidData,
+                            "Request body too large.",
+                        ));
+                    }
+                    self.remaining -= data.len() as u64;
+                    self.buffer = data;
+                    self.position = 0;
+                }
+                Err(_) => return Ok(0),
+            }
+        }
+        let read = buf.len().min(self.buffer.len() - self.position);
+        buf[..read].copy_from_slice(&self.buffer[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
+
+fn perform(
+    request: HttpRequest,
+    policy: DestinationPolicy,
+    limits: HttpLimits,
+    body: mpsc::Receiver<Vec<u8>>,
+    sender: &mpsc::Sender<Vec<u8>>,
+) -> Result<()> {
+    let agent = AgentBuilder::new()
+        .redirects(
+            request
+                .max_redirects
+                .unwrap_or(limits.max_redirects)
+                .min(limits.max_redirects),
+        )
+        .timeout(Duration::from_millis(
+            request
+                .timeout_ms
+                .unwrap_or(limits.timeout_ms)
+                .min(limits.timeout_ms),
+        ))
+        // Every hop, including redirects, is resolved through the destination policy.
+        .resolver(move |netloc: &str| -> io::Result<Vec<SocketAddr>> {
+            let (host, port) = netloc
+                .rsplit_once(':')
+                .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+                .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "Invalid address."))?;
+            policy
+                .resolve(host, port)
+                .map_err(|err| io::Error::new(ErrorKind::PermissionDenied, err.to_string()))
+        })
+        .build();
+    let mut http_request = agent.request(&request.method, &request.url);
+    for (name, value) in &request.headers {
+        http_request = http_request.set(name, value);
+    }
+
+    // Requests without a body are sent as soon as the client ends it, so that they don't go out
+    // with a chunked transfer encoding.
+    let first = body.recv().ok();
+    let result = match first {
+        None => http_request.call(),
+        Some(first) => {
+            if first.len() as u64 > limits.max_request_bytes {
+                bail!("Request body too large.");
+            }
+            http_request.send(BodyReader {
+                receiver: body,
+                remaining: limits.max_request_bytes - first.len() as u64,
+                buffer: first,
+                position: 0,
+            })
+        }
+    };
+    let response = match result {
+        Ok(response) => response,
+        Err(ureq::Error::Status(_, response)) => response,
+        Err(err) => return Err(err.into()),
+    };
+
+    let headers = response
+        .headers_names()
+        .into_iter()
+        .flat_map(|name| {
+            response
+                .all(&name)
+                .into_iter()
+                .map(|value| (name.clone(), value.to_owned()))
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let head = json!({
+        "status": response.status(),
+        "status_text": response.status_text(),
+        "url": response.get_url(),
+        "headers": headers,
+    });
+    let mut data = vec![HTTP_HEAD];
+    data.extend(head.to_string().as_bytes());
+    sender.send(data)?;
+
+    let mut reader = response.into_reader();
+    let mut remaining = limits.max_response_bytes;
+    let mut buffer = [0; 4096];
+    loop {
+        let received = reader.read(&mut buffer)?;
+        if received == 0 {
+            break;
+        }
+        if received as u64 > remaining {
+            bail!("Response body too large.");
+        }
+        remaining -= received as u64;
+        let mut data = vec![HTTP_BODY];
+        data.extend(&buffer[0..received]);
+        sender.send(data)?;
+    }
+    sender.send(vec![HTTP_END])?;
+    Ok(())
+}
@@ -0,0 +1,36 @@
+mod channel;
+mod chargen;
+mod config;
+mod discard;
+mod echo;
+mod http;
+mod policy;
+mod proxy;
+mod proxy_protocol;
+mod registry;
+mod service;
+mod session;
+mod sink;
+mod socket_options;
+mod tcp;
+mod tls;
+mod udp;
+mod websocket;
+
+pub use channel::*;
+pub use chargen::*;
+pub use config::*;
+pub use discard::*;
+pub use echo::*;
+pub use http::*;
+pub use policy::*;
+pub use proxy::*;
+pub use proxy_protocol::*;
+pub use registry::*;
+pub use service::*;
+pub use sink::*;
+pub use socket_options::*;
+pub use tcp::*;
+pub use tls::*;
+pub use udp::*;
+pub use websocket::*;
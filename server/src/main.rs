@@ -1,120 +1,67 @@
-use std::{
-    collections::HashMap,
-    str::{self},
-    time::Duration,
-};
-
-use anyhow::Result;
-use enaia_server::{EnaiaServer, ServerAddrs};
-use rusty_enet::{crc32, Event, Host, HostSettings, Packet, PeerID, RangeCoder};
-
-mod channel;
-mod echo;
-mod tcp;
-mod udp;
+use std::sync::Arc;
 
-pub use channel::*;
-pub use echo::*;
-pub use tcp::*;
-pub use udp::*;
-
-struct Tunnel {
-    channels: HashMap<u8, Channel>,
-}
+use enaia_server::{
+    EnaiaMultiServer, EnaiaServer, EnaiaWebSocketServer, ServerAddrs, TlsTerminator,
+};
+use webrtc_proxy_server::{ProxyServer, ServerConfig, ServiceRegistry};
 
 fn main() {
+    let path = std::env::args().nth(1);
+    let config = Arc::new(match &path {
+        Some(path) => ServerConfig::load(path).expect("could not load config"),
+        None => ServerConfig::default(),
+    });
+    let services = ServiceRegistry::new(config.services.clone());
+    if let Some(path) = path {
+        services.watch(path);
+    }
     let address = ServerAddrs::new(
-        "0.0.0.0:14191"
-            .parse()
-            .expect("could not parse Session address/port"),
-        "0.0.0.0:14192"
-            .parse()
-            .expect("could not parse WebRTC data address/port"),
-        "http://127.0.0.1:14192",
+        config.session_address,
+        config.webrtc_address,
+        &config.public_webrtc_url,
     );
-    let mut network = Host::create(
-        EnaiaServer::new(address).unwrap(),
-        HostSettings {
-            peer_limit: 4095,
-            channel_limit: 255,
-            compressor: Some(Box::new(RangeCoder::new())),
-            checksum: Some(Box::new(crc32)),
-            ..Default::default()
-        },
-    )
-    .unwrap();
-    let mut tunnels = HashMap::<PeerID, Tunnel>::new();
-    loop {
-        while let Some(event) = network.service().unwrap() {
-            match event {
-                Event::Connect { peer, .. } => {
-                    tunnels.insert(
-                        peer.id(),
-                        Tunnel {
-                            channels: HashMap::default(),
-                        },
-                    );
-                }
-                Event::Disconnect { peer, .. } => {
-                    tunnels.remove(&peer.id());
-                }
-                Event::Receive {
-                    peer,
-                    channel_id,
-                    packet,
-                } => {
-                    if let Some(tunnel) = tunnels.get_mut(&peer.id()) {
-                        if let Some(channel) = tunnel.channels.get_mut(&channel_id) {
-                            if let Err(_) = channel.send(packet) {
-                                if let Err(_) = peer.send(channel_id, Packet::reliable(&[0])) {
-                                    peer.disconnect(0);
-                                }
-                            }
-                        } else {
-                            match str::from_utf8(packet.data())
-                                .ok()
-                                .and_then(|str| serde_json::from_str::<ChannelConfig>(str).ok())
-                            {
-                                Some(channel_config) => {
-                                    tunnel
-                                        .channels
-                                        .insert(channel_id, Channel::new(channel_config));
-                                }
-                                None => {
-                                    if let Err(_) = peer.send(channel_id, Packet::reliable(&[0])) {
-                                        peer.disconnect(0);
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-        }
-        for (peer_id, tunnel) in tunnels.iter_mut() {
-            if let Ok(peer) = network.peer_mut(*peer_id) {
-                let mut disconnected_channels = vec![];
-                for (channel_id, channel) in tunnel.channels.iter_mut() {
-                    if let Err(_) = || -> Result<()> {
-                        while let Some(packet) = channel.receive()? {
-                            let packet_kind = packet.kind();
-                            let mut packet_data = vec![1];
-                            packet_data.extend(packet.data());
-                            peer.send(*channel_id, Packet::new(&packet_data, packet_kind))?;
-                        }
-                        Ok(())
-                    }() {
-                        if let Err(_) = peer.send(*channel_id, Packet::reliable(&[0])) {
-                            peer.disconnect(0);
-                        }
-                        disconnected_channels.push(*channel_id);
-                    }
-                }
-                tunnel
-                    .channels
-                    .retain(|channel_id, _| !disconnected_channels.contains(channel_id));
-            }
+    if let Some(https) = &config.https {
+        TlsTerminator::spawn(
+            https.address,
+            config.session_address,
+            https.certificate.clone(),
+            https.key.clone(),
+        )
+        .expect("could not start HTTPS endpoint");
+        if let (Some(address), Some(websocket_address)) =
+            (https.websocket_address, config.websocket_address)
+        {
+            TlsTerminator::spawn(
+                address,
+                websocket_address,
+                https.certificate.clone(),
+                https.key.clone(),
+            )
+            .expect("could not start secure WebSocket endpoint");
         }
-        std::thread::sleep(Duration::from_millis(10));
+    }
+    // One host for both transports, so a client can resume over either and there's one set of
+    // tunnels to count.
+    let websocket = config.websocket_address.map(|address| {
+        EnaiaWebSocketServer::new(address).expect("could not start WebSocket endpoint")
+    });
+    let socket = EnaiaMultiServer::new(
+        EnaiaServer::new(address).expect("could not start WebRTC endpoint"),
+        websocket,
+    );
+    let result = ProxyServer::builder(socket)
+        .config(config)
+        .services(services)
+        // Clients relayed by nginx or the TLS terminator all come from loopback, which isn't their
+        // address, so targets are told the client is unknown rather than given a wrong one.
+        .client_address(|address| {
+            Some(address.socket_address())
+                .filter(|address| !address.ip().to_canonical().is_loopback())
+        })
+        .build()
+        .and_then(|server| server.run());
+    if let Err(err) = result {
+        eprintln!("Proxy server failed: {}", err);
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,186 @@
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::ProxyProtocolVersion;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DestinationPolicy {
+    /// Hosts that channels may reach, either exact names and IP addresses or `*.example.com`
+    /// wildcards. An empty list allows every host.
+    pub allowed_hosts: Vec<String>,
+    /// Refuse loopback, private, link-local and other non-public addresses, even when the host
+    /// itself is allowed.
+    pub deny_private: bool,
+    /// Refuse channels to any destination but a configured service, so the services are the
+    /// allowlist. Channels without a destination, like Echo, and custom channels still work.
+    pub services_only: bool,
+    /// Destinations that are sent a PROXY protocol header with the client's address. The first
+    /// rule that matches applies.
+    pub proxy_protocol: Vec<ProxyProtocolRule>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyProtocolRule {
+    /// Hosts the rule applies to, matched like `allowed_hosts`. TCP and UDP channels are matched
+    /// by IP address, TLS channels by the host they were given.
+    pub hosts: Vec<String>,
+    /// Ports the rule applies to. An empty list matches every port.
+    #[serde(default)]
+    pub ports: Vec<u16>,
+    pub version: ProxyProtocolVersion,
+}
+
+/// Whether `host` matches `pattern`, ignoring case as DNS does.
+pub(crate) fn host_matches(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let host = host.to_ascii_lowercase();
+    if let Some(suffix) = pattern.strip_prefix("*.") {
+        host.strip_suffix(suffix)
+            .and_then(|subdomain| subdomain.strip_suffix('.'))
+            .is_some_and(|subdomain| !subdomain.is_empty())
+    } else {
+        pattern == host
+    }
+}
+
+impl DestinationPolicy {
+    pub fn check_host(&self, host: &str) -> Result<()> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if self.allowed_hosts.is_empty()
+            || self
+                .allowed_hosts
+                .iter()
+                .any(|allowed| host_matches(allowed, host))
+        {
+            Ok(())
+        } else {
+            bail!("Host {} is not allowed.", host);
+        }
+    }
+
+    pub fn check_address(&self, address: SocketAddr) -> Result<()> {
+        if self.deny_private && !is_public(address.ip()) {
+            bail!("Address {} is not allowed.", address);
+        }
+        Ok(())
+    }
+
+    pub fn check(&self, address: SocketAddr) -> Result<()> {
+        self.check_host(&address.ip().to_string())?;
+        self.check_address(address)
+    }
+
+    /// The PROXY protocol version to use toward `host` on `port`, if any rule asks for one.
+    pub fn proxy_protocol(&self, host: &str, port: u16) -> Option<ProxyProtocolVersion> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        self.proxy_protocol
+            .iter()
+            .find(|rule| {
+                rule.hosts.iter().any(|pattern| host_matches(pattern, host))
+                    && (rule.ports.is_empty() || rule.ports.contains(&port))
+            })
+            .map(|rule| rule.version)
+    }
+
+    /// Resolves `host` and keeps only the addresses this policy allows, so that a name can't be
+    /// used to smuggle a connection to a denied address.
+    pub fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        self.check_host(host)?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let addresses = (host, port)
+            .to_socket_addrs()?
+            .filter(|address| self.check_address(*address).is_ok())
+            .collect::<Vec<_>>();
+        if addresses.is_empty() {
+            bail!("Host {} has no allowed addresses.", host);
+        }
+        Ok(addresses)
+    }
+
+    /// Like [`resolve`](Self::resolve), for an address given as `host:port`.
+    pub fn resolve_address(&self, address: &str) -> Result<Vec<SocketAddr>> {
+        let Some((host, port)) = address
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+        else {
+            bail!("Invalid address {}.", address);
+        };
+        self.resolve(host, port)
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || octets[0] == 0
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+                || octets[0] >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let segments = ip.segments();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                || (segments[0] & 0xfe00) == 0xfc00
+                || (segments[0] & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_hosts_match_ignoring_case() {
+        assert!(host_matches("example.com", "example.com"));
+        assert!(host_matches("Example.COM", "example.com"));
+        assert!(host_matches("example.com", "EXAMPLE.com"));
+        assert!(host_matches("10.0.0.1", "10.0.0.1"));
+        assert!(!host_matches("example.com", "www.example.com"));
+        assert!(!host_matches("example.com", "example.org"));
+    }
+
+    #[test]
+    fn wildcards_match_subdomains_ignoring_case() {
+        assert!(host_matches("*.example.com", "www.example.com"));
+        assert!(host_matches("*.example.com", "a.b.example.com"));
+        assert!(host_matches("*.Example.com", "WWW.EXAMPLE.COM"));
+        assert!(host_matches("*.example.com", "Api.Example.Com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "badexample.com"));
+        assert!(!host_matches("*.example.com", ".example.com"));
+    }
+
+    #[test]
+    fn proxy_protocol_rules_match_hosts_ignoring_case() {
+        let policy = DestinationPolicy {
+            proxy_protocol: vec![ProxyProtocolRule {
+                hosts: vec!["*.Internal".to_owned()],
+                ports: vec![443],
+                version: ProxyProtocolVersion::V2,
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.proxy_protocol("db.INTERNAL", 443),
+            Some(ProxyProtocolVersion::V2)
+        );
+        assert_eq!(policy.proxy_protocol("db.internal", 80), None);
+        assert_eq!(policy.proxy_protocol("db.external", 443), None);
+    }
+}
@@ -0,0 +1,572 @@
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    str::{self},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use rusty_enet::{crc32, Event, Host, HostSettings, Packet, Peer, PeerID, RangeCoder, Socket};
+use serde::de::DeserializeOwned;
+
+use crate::{
+    session::{Session, SessionToken},
+    Channel, ChannelConfig, ChannelEvent, ChannelRegistry, ChannelStats, ChannelStream,
+    ServerConfig, ServiceRegistry,
+};
+
+/// Set in the connect data of a client that wants to resume its tunnel if the transport is lost.
+/// The server answers with a [`FRAME_SESSION`] holding the token to resume it with. Only framed
+/// tunnels can be resumed.
+const CONNECT_SESSION: u32 = 1 << 0;
+/// Set in the connect data of a client resuming a tunnel. Its first packet is a [`FRAME_SESSION`]
+/// with the token and how many packets it received on each channel, and the server answers with
+/// one holding its own counts. Both then send again what the other is missing.
+const CONNECT_RESUME: u32 = 1 << 1;
+/// Set in the connect data of a client that frames what it sends, see [`FRAME_DATA`]. Clients
+/// without it send bare data, can't close or shut down channels, and aren't sent the frames they
+/// don't know, so channels that reach EOF close for them.
+const CONNECT_FRAMED: u32 = 1 << 2;
+/// How long a client resuming a tunnel has to send its token.
+const RESUME_TIMEOUT: Duration = Duration::from_secs(5);
+/// Disconnect data of a client that's done with its tunnel, as opposed to one that timed out.
+const DISCONNECT_CLOSED: u32 = 1;
+/// Disconnect data the server sends when asked to resume a session it doesn't have.
+const DISCONNECT_UNKNOWN_SESSION: u32 = 2;
+/// Disconnect data the server sends to peers turned away by [`ProxyServerBuilder::allow_peer`].
+const DISCONNECT_REFUSED: u32 = 3;
+
+/// First byte of every packet on a channel after the config that opens it, in both directions, or
+/// only from the server if the client didn't set [`CONNECT_FRAMED`]. A bare close frame from the
+/// client closes the channel, and the server answers with one once the target is closed, after
+/// which the channel id can be opened again.
+pub const FRAME_CLOSE: u8 = 0;
+pub const FRAME_DATA: u8 = 1;
+/// Nothing more will be sent in this direction, though the other still works. The server sends it
+/// when a TCP target reads EOF, and the client to shut down writing to the target.
+pub const FRAME_SHUTDOWN: u8 = 2;
+/// Asks the server for the channel's [`ChannelStats`], which it sends back as JSON after the same
+/// byte.
+pub const FRAME_STATS: u8 = 3;
+/// Sets up resumption, on channel 0 and outside the channel's data. See [`CONNECT_SESSION`] and
+/// [`CONNECT_RESUME`].
+pub const FRAME_SESSION: u8 = 4;
+/// How many reliable packets were received on the channel so far, as a big-endian u32, so the
+/// other side of a resumable tunnel can stop keeping them. Sent unreliably, in both directions.
+pub const FRAME_ACK: u8 = 5;
+
+fn peer_stats<S: Socket>(peer: &Peer<S>, stats: ChannelStats) -> ChannelStats {
+    ChannelStats {
+        round_trip_time_ms: peer.round_trip_time().as_millis() as u64,
+        round_trip_time_variance_ms: peer.round_trip_time_variance().as_millis() as u64,
+        packet_loss: peer.packet_loss(),
+        ..stats
+    }
+}
+
+/// Keeps `packet` for a resume, ending the session if that's more than it may keep.
+fn keep(session: &mut Option<Session>, channel_id: u8, packet: &Packet) {
+    if session
+        .as_mut()
+        .is_some_and(|session| !session.sent(channel_id, packet))
+    {
+        *session = None;
+    }
+}
+
+struct Tunnel {
+    /// The client frames what it sends.
+    framed: bool,
+    /// Set while the tunnel can be resumed.
+    session: Option<Session>,
+    /// Where the client connected from, as of its latest connection.
+    client_address: Option<SocketAddr>,
+    channels: HashMap<u8, Channel>,
+}
+
+impl Tunnel {
+    /// Sends `packet` to the client, keeping it for a resume.
+    fn send<S: Socket>(
+        &mut self,
+        peer: &mut Peer<S>,
+        channel_id: u8,
+        packet: Packet,
+    ) -> Result<()> {
+        keep(&mut self.session, channel_id, &packet);
+        Ok(peer.send(channel_id, packet)?)
+    }
+
+    /// Hands what the channels received to `send`, framed for the client and kept for a resume,
+    /// and drops the channels whose worker is done, with a close frame. Returns false if the
+    /// client couldn't be told about a closed channel.
+    fn forward(&mut self, mut send: impl FnMut(u8, Packet) -> Result<()>) -> bool {
+        let Self {
+            framed,
+            session,
+            channels,
+            ..
+        } = self;
+        let mut send = |channel_id: u8, packet: Packet| {
+            keep(session, channel_id, &packet);
+            send(channel_id, packet)
+        };
+        let mut notified = true;
+        let mut disconnected_channels = vec![];
+        for (channel_id, channel) in channels.iter_mut() {
+            if (|| -> Result<()> {
+                while let Some(event) = channel.receive()? {
+                    if channel.closing() {
+                        // The client is done with the channel.
+                        continue;
+                    }
+                    match event {
+                        // Clients take an empty data frame to mean the channel is connected.
+                        ChannelEvent::Connected => {
+                            send(*channel_id, Packet::reliable(&[FRAME_DATA]))?
+                        }
+                        ChannelEvent::Data(packet) => {
+                            let packet_kind = packet.kind();
+                            let mut packet_data = vec![FRAME_DATA];
+                            packet_data.extend(packet.data());
+                            send(*channel_id, Packet::new(&packet_data, packet_kind))?;
+                        }
+                        ChannelEvent::Shutdown if *framed => {
+                            send(*channel_id, Packet::reliable(&[FRAME_SHUTDOWN]))?
+                        }
+                        ChannelEvent::Shutdown => channel.close(),
+                    }
+                }
+                Ok(())
+            })()
+            .is_err()
+            {
+                if send(*channel_id, Packet::reliable(&[FRAME_CLOSE])).is_err() {
+                    notified = false;
+                }
+                disconnected_channels.push(*channel_id);
+            }
+        }
+        channels.retain(|channel_id, _| !disconnected_channels.contains(channel_id));
+        notified
+    }
+}
+
+/// The tunnel of a client that timed out, kept open in case it reconnects with its token. What
+/// the channels receive in the meantime is kept by its session, to be sent on resume.
+struct SuspendedTunnel {
+    tunnel: Tunnel,
+    since: Instant,
+}
+
+type PeerFilter<S> = Box<dyn FnMut(&<S as Socket>::PeerAddress) -> bool>;
+type ChannelFilter = Box<dyn FnMut(&ChannelConfig) -> bool>;
+type ClientAddress<S> = Box<dyn Fn(&<S as Socket>::PeerAddress) -> Option<SocketAddr>>;
+
+/// Relays the tunnels of every peer on one socket, e.g. an
+/// [`EnaiaServer`](enaia_server::EnaiaServer) or an
+/// [`EnaiaWebSocketServer`](enaia_server::EnaiaWebSocketServer). To serve several transports with
+/// one set of tunnels, so clients can resume over either, combine them into one socket like
+/// [`EnaiaMultiServer`](enaia_server::EnaiaMultiServer).
+///
+/// Embedders either hand it a thread with [`run`](Self::run) or call [`poll`](Self::poll) from
+/// their own loop.
+pub struct ProxyServer<S: Socket> {
+    network: Host<S>,
+    config: Arc<ServerConfig>,
+    registry: Arc<ChannelRegistry>,
+    services: ServiceRegistry,
+    grace_period: Duration,
+    tunnels: HashMap<PeerID, Tunnel>,
+    suspended: HashMap<SessionToken, SuspendedTunnel>,
+    /// Peers that connected to resume a tunnel and haven't sent its token yet.
+    resuming: HashMap<PeerID, Instant>,
+    allow_peer: Option<PeerFilter<S>>,
+    allow_channel: Option<ChannelFilter>,
+    client_address: Option<ClientAddress<S>>,
+}
+
+pub struct ProxyServerBuilder<S: Socket> {
+    socket: S,
+    config: Arc<ServerConfig>,
+    registry: ChannelRegistry,
+    services: Option<ServiceRegistry>,
+    host_settings: HostSettings,
+    allow_peer: Option<PeerFilter<S>>,
+    allow_channel: Option<ChannelFilter>,
+    client_address: Option<ClientAddress<S>>,
+}
+
+impl<S: Socket> ProxyServerBuilder<S> {
+    /// Defaults to [`ServerConfig::default`]. The addresses in it are ignored here, since they're
+    /// for whoever creates the transport.
+    pub fn config(mut self, config: impl Into<Arc<ServerConfig>>) -> Self {
+        self.config = config.into();
+        self
+    }
+
+    /// Defaults to 4095 peers with 255 channels each, compressed and checksummed. Clients must
+    /// agree on the compressor and checksum.
+    pub fn host_settings(mut self, host_settings: HostSettings) -> Self {
+        self.host_settings = host_settings;
+        self
+    }
+
+    /// Replaces the custom channel kinds registered so far.
+    pub fn registry(mut self, registry: ChannelRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Registers a custom channel kind, see [`ChannelRegistry::register`].
+    pub fn channel<C, F>(mut self, name: impl Into<String>, factory: F) -> Self
+    where
+        C: DeserializeOwned,
+        F: Fn(C) -> Result<Box<dyn ChannelStream>> + Send + Sync + 'static,
+    {
+        self.registry.register(name, factory);
+        self
+    }
+
+    /// Shares `services` with other servers, so connection counts cover all of them. Defaults to
+    /// a registry of its own with the services in the config.
+    pub fn services(mut self, services: ServiceRegistry) -> Self {
+        self.services = Some(services);
+        self
+    }
+
+    /// Turns away peers `filter` returns false for, on top of the destination policy.
+    pub fn allow_peer(mut self, filter: impl FnMut(&S::PeerAddress) -> bool + 'static) -> Self {
+        self.allow_peer = Some(Box::new(filter));
+        self
+    }
+
+    /// Refuses to open channels `filter` returns false for, on top of the destination policy.
+    pub fn allow_channel(mut self, filter: impl FnMut(&ChannelConfig) -> bool + 'static) -> Self {
+        self.allow_channel = Some(Box::new(filter));
+        self
+    }
+
+    /// Maps the transport's peer addresses to the socket addresses of clients, for targets that
+    /// are sent a PROXY protocol header. Without it, those targets are told the client is unknown.
+    pub fn client_address(
+        mut self,
+        map: impl Fn(&S::PeerAddress) -> Option<SocketAddr> + 'static,
+    ) -> Self {
+        self.client_address = Some(Box::new(map));
+        self
+    }
+
+    pub fn build(self) -> Result<ProxyServer<S>> {
+        Ok(ProxyServer {
+            network: Host::create(self.socket, self.host_settings)?,
+            grace_period: Duration::from_millis(self.config.resumption.grace_period_ms),
+            services: self
+                .services
+                .unwrap_or_else(|| ServiceRegistry::new(self.config.services.clone())),
+            config: self.config,
+            registry: Arc::new(self.registry),
+            tunnels: HashMap::new(),
+            suspended: HashMap::new(),
+            resuming: HashMap::new(),
+            allow_peer: self.allow_peer,
+            allow_channel: self.allow_channel,
+            client_address: self.client_address,
+        })
+    }
+}
+
+impl<S: Socket> ProxyServer<S> {
+    pub fn builder(socket: S) -> ProxyServerBuilder<S> {
+        ProxyServerBuilder {
+            socket,
+            config: Arc::new(ServerConfig::default()),
+            registry: ChannelRegistry::default(),
+            services: None,
+            host_settings: HostSettings {
+                peer_limit: 4095,
+                channel_limit: 255,
+                compressor: Some(Box::new(RangeCoder::new())),
+                checksum: Some(Box::new(crc32)),
+                ..Default::default()
+            },
+            allow_peer: None,
+            allow_channel: None,
+            client_address: None,
+        }
+    }
+
+    pub fn host(&self) -> &Host<S> {
+        &self.network
+    }
+
+    /// The same numbers clients get by sending a stats frame.
+    pub fn channel_stats(&self, peer_id: PeerID, channel_id: u8) -> Option<ChannelStats> {
+        let channel = self.tunnels.get(&peer_id)?.channels.get(&channel_id)?;
+        let peer = self.network.peer(peer_id).ok()?;
+        Some(peer_stats(peer, channel.stats()))
+    }
+
+    /// Polls every 10 milliseconds until the transport itself fails. Failures to reach one peer
+    /// only affect that peer.
+    pub fn run(mut self) -> Result<()> {
+        loop {
+            self.poll()?;
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Handles everything the transport received and relays what the channels received, without
+    /// blocking.
+    pub fn poll(&mut self) -> Result<()> {
+        let mut replaced_peers = vec![];
+        let client_address = |peer: &Peer<S>| {
+            let map = self.client_address.as_ref()?;
+            map(&peer.address()?)
+        };
+        while let Some(event) = self.network.service()? {
+            match event {
+                Event::Connect { peer, .. }
+                    if self.allow_peer.as_mut().is_some_and(|allow_peer| {
+                        !peer.address().is_some_and(|address| allow_peer(&address))
+                    }) =>
+                {
+                    peer.disconnect(DISCONNECT_REFUSED);
+                }
+                Event::Connect { peer, data } if data & CONNECT_RESUME != 0 => {
+                    // The token comes in the first packet, since it doesn't fit the connect data.
+                    self.resuming.insert(peer.id(), Instant::now());
+                }
+                Event::Connect { peer, data } => {
+                    let mut tunnel = Tunnel {
+                        framed: data & CONNECT_FRAMED != 0,
+                        session: None,
+                        client_address: client_address(peer),
+                        channels: HashMap::default(),
+                    };
+                    if tunnel.framed && data & CONNECT_SESSION != 0 && !self.grace_period.is_zero()
+                    {
+                        let session = Session::new(self.config.resumption.max_buffered_bytes);
+                        let mut data = vec![FRAME_SESSION];
+                        data.extend(session.token);
+                        if peer.send(0, Packet::reliable(&data)).is_err() {
+                            peer.disconnect(0);
+                        }
+                        tunnel.session = Some(session);
+                    }
+                    self.tunnels.insert(peer.id(), tunnel);
+                }
+                Event::Disconnect { peer, data } => {
+                    self.resuming.remove(&peer.id());
+                    if let Some(tunnel) = self.tunnels.remove(&peer.id()) {
+                        let token = tunnel.session.as_ref().map(|session| session.token);
+                        if let Some(token) = token
+                            .filter(|_| data != DISCONNECT_CLOSED && !tunnel.channels.is_empty())
+                        {
+                            self.suspended.insert(
+                                token,
+                                SuspendedTunnel {
+                                    tunnel,
+                                    since: Instant::now(),
+                                },
+                            );
+                        }
+                    }
+                }
+                Event::Receive {
+                    peer,
+                    channel_id,
+                    packet,
+                } if self.resuming.remove(&peer.id()).is_some() => {
+                    let token = match packet.data().split_first() {
+                        Some((&FRAME_SESSION, data)) if channel_id == 0 => data
+                            .get(..16)
+                            .and_then(|token| SessionToken::try_from(token).ok())
+                            .map(|token| (token, &data[16..])),
+                        _ => None,
+                    };
+                    let Some((token, counts)) = token else {
+                        peer.disconnect(DISCONNECT_UNKNOWN_SESSION);
+                        continue;
+                    };
+                    let tunnel = match self.suspended.remove(&token) {
+                        Some(suspended_tunnel) => Some(suspended_tunnel.tunnel),
+                        None => {
+                            let previous_peer = self
+                                .tunnels
+                                .iter()
+                                .find(|(_, tunnel)| {
+                                    tunnel
+                                        .session
+                                        .as_ref()
+                                        .is_some_and(|session| session.token == token)
+                                })
+                                .map(|(peer_id, _)| *peer_id);
+                            // The client noticed the transport was gone before we did. Only it
+                            // has the token, and it only resumes once its own transport failed,
+                            // so the previous peer is gone too.
+                            previous_peer.and_then(|previous_peer| {
+                                replaced_peers.push(previous_peer);
+                                self.tunnels.remove(&previous_peer)
+                            })
+                        }
+                    };
+                    let resumed = tunnel.and_then(|mut tunnel| {
+                        let session = tunnel.session.as_mut()?;
+                        let missing = session.resume(counts)?;
+                        let mut reply = vec![FRAME_SESSION];
+                        reply.extend(session.received_counts());
+                        Some((tunnel, reply, missing))
+                    });
+                    let Some((mut tunnel, reply, missing)) = resumed else {
+                        peer.disconnect(DISCONNECT_UNKNOWN_SESSION);
+                        continue;
+                    };
+                    tunnel.client_address = client_address(peer);
+                    for (channel_id, packet) in
+                        [(0, Packet::reliable(&reply))].into_iter().chain(missing)
+                    {
+                        if peer.send(channel_id, packet).is_err() {
+                            peer.disconnect(0);
+                            break;
+                        }
+                    }
+                    self.tunnels.insert(peer.id(), tunnel);
+                }
+                Event::Receive {
+                    peer,
+                    channel_id,
+                    packet,
+                } => {
+                    if let Some(tunnel) = self.tunnels.get_mut(&peer.id()) {
+                        if let Some(session) = &mut tunnel.session {
+                            session.received(channel_id, &packet);
+                        }
+                        // Dropped without a session too, since the client keeps acknowledging
+                        // after the session ended on overflow, not knowing it did.
+                        if let (true, Some((&FRAME_ACK, received))) =
+                            (tunnel.framed, packet.data().split_first())
+                        {
+                            if let (Some(session), Ok(received)) =
+                                (&mut tunnel.session, <[u8; 4]>::try_from(received))
+                            {
+                                session.acknowledge(channel_id, u32::from_be_bytes(received));
+                            }
+                            continue;
+                        }
+                        if let Some(channel) = tunnel.channels.get_mut(&channel_id) {
+                            let sent = if !tunnel.framed {
+                                channel.send(ChannelEvent::Data(packet)).is_ok()
+                            } else {
+                                match packet.data().split_first() {
+                                    Some((&FRAME_DATA, data)) => channel
+                                        .send(ChannelEvent::Data(Packet::new(data, packet.kind())))
+                                        .is_ok(),
+                                    Some((&FRAME_SHUTDOWN, [])) => {
+                                        channel.send(ChannelEvent::Shutdown).is_ok()
+                                    }
+                                    Some((&FRAME_STATS, [])) => {
+                                        if serde_json::to_vec(&peer_stats(peer, channel.stats()))
+                                            .map_err(anyhow::Error::from)
+                                            .and_then(|stats| {
+                                                let mut data = vec![FRAME_STATS];
+                                                data.extend(stats);
+                                                tunnel.send(
+                                                    peer,
+                                                    channel_id,
+                                                    Packet::reliable(&data),
+                                                )
+                                            })
+                                            .is_err()
+                                        {
+                                            peer.disconnect(0);
+                                        }
+                                        true
+                                    }
+                                    _ => false,
+                                }
+                            };
+                            if !sent {
+                                // The close frame goes out from `forward` once the worker has
+                                // closed the target.
+                                if let Some(channel) = tunnel.channels.get_mut(&channel_id) {
+                                    channel.close();
+                                }
+                            }
+                        } else if tunnel.framed && packet.data() == [FRAME_CLOSE] {
+                            // The channel closed on our side while the client was closing it, so
+                            // the client already has its close frame.
+                        } else {
+                            match str::from_utf8(packet.data())
+                                .ok()
+                                .and_then(|str| serde_json::from_str::<ChannelConfig>(str).ok())
+                                .filter(|channel_config| {
+                                    self.allow_channel
+                                        .as_mut()
+                                        .is_none_or(|allow_channel| allow_channel(channel_config))
+                                }) {
+                                Some(channel_config) => {
+                                    tunnel.channels.insert(
+                                        channel_id,
+                                        Channel::new(
+                                            channel_config,
+                                            self.config.clone(),
+                                            self.registry.clone(),
+                                            self.services.clone(),
+                                            tunnel.client_address,
+                                        ),
+                                    );
+                                }
+                                None => {
+                                    if tunnel
+                                        .send(peer, channel_id, Packet::reliable(&[FRAME_CLOSE]))
+                                        .is_err()
+                                    {
+                                        peer.disconnect(0);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for peer_id in replaced_peers {
+            if let Ok(peer) = self.network.peer_mut(peer_id) {
+                peer.reset();
+            }
+        }
+        for (peer_id, tunnel) in self.tunnels.iter_mut() {
+            if let Ok(peer) = self.network.peer_mut(*peer_id) {
+                let acks = tunnel
+                    .session
+                    .as_mut()
+                    .map(Session::acks)
+                    .unwrap_or_default();
+                if !tunnel.forward(|channel_id, packet| Ok(peer.send(channel_id, packet)?))
+                    || acks
+                        .into_iter()
+                        .any(|(channel_id, packet)| peer.send(channel_id, packet).is_err())
+                {
+                    peer.disconnect(0);
+                }
+            }
+        }
+        self.resuming.retain(|peer_id, since| {
+            if since.elapsed() < RESUME_TIMEOUT {
+                return true;
+            }
+            if let Ok(peer) = self.network.peer_mut(*peer_id) {
+                peer.disconnect(DISCONNECT_UNKNOWN_SESSION);
+            }
+            false
+        });
+        let grace_period = self.grace_period;
+        self.suspended.retain(|_, suspended_tunnel| {
+            suspended_tunnel.tunnel.forward(|_, _| Ok(()));
+            suspended_tunnel.tunnel.session.is_some()
+                && suspended_tunnel.since.elapsed() < grace_period
+        });
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use std::net::{IpAddr, SocketAddr};
+
+use serde::{Deserialize, Serialize};
+
+/// Signature that starts every v2 header.
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V2_COMMAND_LOCAL: u8 = 0x20;
+const V2_COMMAND_PROXY: u8 = 0x21;
+const V2_FAMILY_UNSPECIFIED: u8 = 0x00;
+const V2_FAMILY_INET: u8 = 0x10;
+const V2_FAMILY_INET6: u8 = 0x20;
+const V2_TRANSPORT_STREAM: u8 = 0x01;
+const V2_TRANSPORT_DGRAM: u8 = 0x02;
+
+/// HAProxy PROXY protocol version, for telling targets which client a connection is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProxyProtocolVersion {
+    /// The human-readable header, TCP only.
+    V1,
+    /// The binary header, which also has a form for UDP.
+    V2,
+}
+
+impl ProxyProtocolVersion {
+    /// The header announcing a connection from `source` to `destination`. Without a source, v1
+    /// sends `UNKNOWN` and v2 the `LOCAL` command, which targets treat as a connection from the
+    /// proxy itself. Datagrams always get a v2 header, since v1 has no form for them.
+    pub fn header(
+        self,
+        source: Option<SocketAddr>,
+        destination: SocketAddr,
+        datagram: bool,
+    ) -> Vec<u8> {
+        if self == Self::V1 && !datagram {
+            header_v1(source, destination)
+        } else {
+            header_v2(source, destination, datagram)
+        }
+    }
+}
+
+/// Both addresses in the same family, mapping IPv4 into IPv6 if only one of them is IPv6.
+fn same_family(source: SocketAddr, destination: SocketAddr) -> (SocketAddr, SocketAddr) {
+    let to_v6 = |address: SocketAddr| match address.ip() {
+        IpAddr::V4(ip) => SocketAddr::new(IpAddr::V6(ip.to_ipv6_mapped()), address.port()),
+        IpAddr::V6(_) => address,
+    };
+    if source.is_ipv4() == destination.is_ipv4() {
+        (source, destination)
+    } else {
+        (to_v6(source), to_v6(destination))
+    }
+}
+
+fn header_v1(source: Option<SocketAddr>, destination: SocketAddr) -> Vec<u8> {
+    let Some(source) = source else {
+        return b"PROXY UNKNOWN\r\n".to_vec();
+    };
+    let (source, destination) = same_family(source, destination);
+    format!(
+        "PROXY {} {} {} {} {}\r\n",
+        if source.is_ipv4() { "TCP4" } else { "TCP6" },
+        source.ip(),
+        destination.ip(),
+        source.port(),
+        destination.port(),
+    )
+    .into_bytes()
+}
+
+fn header_v2(source: Option<SocketAddr>, destination: SocketAddr, datagram: bool) -> Vec<u8> {
+    let mut header = V2_SIGNATURE.to_vec();
+    let Some(source) = source else {
+        header.extend([V2_COMMAND_LOCAL, V2_FAMILY_UNSPECIFIED, 0, 0]);
+        return header;
+    };
+    let (source, destination) = same_family(source, destination);
+    let transport = if datagram {
+        V2_TRANSPORT_DGRAM
+    } else {
+        V2_TRANSPORT_STREAM
+    };
+    let mut addresses = vec![];
+    let family = match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            addresses.extend(source.octets());
+            addresses.extend(destination.octets());
+            V2_FAMILY_INET
+        }
+        (IpAddr::V6(source), IpAddr::V6(destination)) => {
+            addresses.extend(source.octets());
+            addresses.extend(destination.octets());
+            V2_FAMILY_INET6
+        }
+        _ => unreachable!(),
+    };
+    addresses.extend(source.port().to_be_bytes());
+    addresses.extend(destination.port().to_be_bytes());
+    header.extend([V2_COMMAND_PROXY, family | transport]);
+    header.extend((addresses.len() as u16).to_be_bytes());
+    header.extend(addresses);
+    header
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn v1_ipv4() {
+        let header = header_v1(
+            Some("192.168.0.1:56324".parse().unwrap()),
+            "192.168.0.11:443".parse().unwrap(),
+        );
+        assert_eq!(header, b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n");
+    }
+
+    #[test]
+    fn v1_ipv6() {
+        let header = header_v1(
+            Some("[2001:db8::1]:56324".parse().unwrap()),
+            "[2001:db8::11]:443".parse().unwrap(),
+        );
+        assert_eq!(header, b"PROXY TCP6 2001:db8::1 2001:db8::11 56324 443\r\n");
+    }
+
+    #[test]
+    fn v1_maps_mixed_families_to_ipv6() {
+        let header = header_v1(
+            Some("192.168.0.1:56324".parse().unwrap()),
+            "[2001:db8::11]:443".parse().unwrap(),
+        );
+        assert_eq!(
+            header,
+            b"PROXY TCP6 ::ffff:192.168.0.1 2001:db8::11 56324 443\r\n"
+        );
+    }
+
+    #[test]
+    fn v1_unknown_without_source() {
+        assert_eq!(
+            header_v1(None, "192.168.0.11:443".parse().unwrap()),
+            b"PROXY UNKNOWN\r\n"
+        );
+    }
+
+    #[test]
+    fn v2_ipv4() {
+        let header = header_v2(
+            Some("192.168.0.1:56324".parse().unwrap()),
+            "192.168.0.11:443".parse().unwrap(),
+            false,
+        );
+        let mut expected = V2_SIGNATURE.to_vec();
+        expected.extend([0x21, 0x11, 0, 12]);
+        expected.extend([192, 168, 0, 1, 192, 168, 0, 11]);
+        expected.extend([0xdc, 0x04, 0x01, 0xbb]);
+        assert_eq!(header, expected);
+    }
+
+    #[test]
+    fn v2_ipv6_datagram() {
+        let header = header_v2(
+            Some("[2001:db8::1]:56324".parse().unwrap()),
+            "[2001:db8::11]:443".parse().unwrap(),
+            true,
+        );
+        let mut expected = V2_SIGNATURE.to_vec();
+        expected.extend([0x21, 0x22, 0, 36]);
+        expected.extend([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        expected.extend([
+            0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x11,
+        ]);
+        expected.extend([0xdc, 0x04, 0x01, 0xbb]);
+        assert_eq!(header, expected);
+    }
+
+    #[test]
+    fn v2_maps_mixed_families_to_ipv6() {
+        let header = header_v2(
+            Some("[2001:db8::1]:56324".parse().unwrap()),
+            "192.168.0.11:443".parse().unwrap(),
+            false,
+        );
+        let mut expected = V2_SIGNATURE.to_vec();
+        expected.extend([0x21, 0x21, 0, 36]);
+        expected.extend([0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        expected.extend([0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 192, 168, 0, 11]);
+        expected.extend([0xdc, 0x04, 0x01, 0xbb]);
+        assert_eq!(header, expected);
+    }
+
+    #[test]
+    fn v2_local_without_source() {
+        let header = header_v2(None, "192.168.0.11:443".parse().unwrap(), true);
+        let mut expected = V2_SIGNATURE.to_vec();
+        expected.extend([0x20, 0x00, 0, 0]);
+        assert_eq!(header, expected);
+    }
+
+    #[test]
+    fn datagrams_always_get_v2() {
+        let destination = "192.168.0.11:443".parse().unwrap();
+        assert_eq!(
+            ProxyProtocolVersion::V1.header(None, destination, true),
+            header_v2(None, destination, true)
+        );
+    }
+}
@@ -0,0 +1,47 @@
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::ChannelStream;
+
+type ChannelFactory = Arc<dyn Fn(Value) -> Result<Box<dyn ChannelStream>> + Send + Sync>;
+
+/// Application-specific channel kinds, opened by clients with
+/// `{"Custom": {"name": ..., "config": ...}}`.
+///
+/// Factories run on the channel's own thread, so they may block while connecting, like the
+/// built-in TCP and TLS channels do. The destination policy doesn't apply to what they connect
+/// to; that's up to the factory.
+#[derive(Clone, Default)]
+pub struct ChannelRegistry {
+    factories: HashMap<String, ChannelFactory>,
+}
+
+impl ChannelRegistry {
+    /// Registers `factory` under `name`, replacing any earlier factory with that name. Clients'
+    /// configs are deserialized into `C` first, and fail to open the channel if they don't match.
+    pub fn register<C, F>(&mut self, name: impl Into<String>, factory: F)
+    where
+        C: DeserializeOwned,
+        F: Fn(C) -> Result<Box<dyn ChannelStream>> + Send + Sync + 'static,
+    {
+        self.factories.insert(
+            name.into(),
+            Arc::new(move |config| factory(serde_json::from_value(config)?)),
+        );
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.factories.contains_key(name)
+    }
+
+    pub fn open(&self, name: &str, config: Value) -> Result<Box<dyn ChannelStream>> {
+        let factory = self
+            .factories
+            .get(name)
+            .ok_or_else(|| anyhow!("Unknown channel type {}.", name))?;
+        factory(config)
+    }
+}
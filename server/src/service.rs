@@ -0,0 +1,429 @@
+use std::{
+    collections::HashMap,
+    fs,
+    net::{SocketAddr, TcpStream},
+    path::PathBuf,
+    sync::{Arc, Mutex, Weak},
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Result};
+use rusty_enet::Packet;
+use serde::{Deserialize, Serialize};
+
+use crate::{ChannelStatus, ChannelStream, ServerConfig, SocketOptions};
+
+/// How often the health checker looks for backends that are due a check.
+const HEALTH_CHECK_TICK: Duration = Duration::from_millis(500);
+/// How often the config file is checked for changes by [`ServiceRegistry::watch`].
+const CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How long connecting to a backend may take, for services without a health check to take the
+/// timeout from.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A named pool of backends clients open with `{"Service": name}`, without knowing their
+/// addresses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceConfig {
+    pub protocol: ServiceProtocol,
+    pub backends: Vec<SocketAddr>,
+    #[serde(default)]
+    pub balancing: Balancing,
+    /// Checks backends in the background and skips the ones that fail. Without it, every backend
+    /// is tried.
+    #[serde(default)]
+    pub health_check: Option<HealthCheck>,
+    /// Socket options for the sockets to the backends. They're set by the operator, so they
+    /// aren't clamped to `socket_limits`.
+    #[serde(default)]
+    pub options: SocketOptions,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ServiceProtocol {
+    Tcp,
+    Udp,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Balancing {
+    #[default]
+    RoundRobin,
+    /// The backend with the fewest open channels.
+    LeastConnections,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthCheck {
+    pub interval_ms: u64,
+    pub timeout_ms: u64,
+    /// Port to check with a TCP connection, for UDP backends with a TCP status port. Defaults to
+    /// the backend's own port. UDP backends without one aren't checked.
+    pub port: Option<u16>,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            interval_ms: 5000,
+            timeout_ms: 2000,
+            port: None,
+        }
+    }
+}
+
+struct Backend {
+    address: SocketAddr,
+    healthy: bool,
+    connections: usize,
+    checked_at: Option<Instant>,
+}
+
+struct Service {
+    config: ServiceConfig,
+    backends: Vec<Backend>,
+    next: usize,
+}
+
+impl Service {
+    fn new(config: ServiceConfig) -> Self {
+        Self {
+            backends: config
+                .backends
+                .iter()
+                .map(|address| Backend {
+                    address: *address,
+                    healthy: true,
+                    connections: 0,
+                    checked_at: None,
+                })
+                .collect(),
+            config,
+            next: 0,
+        }
+    }
+
+    /// How long connecting to a backend may take.
+    fn connect_timeout(&self) -> Duration {
+        self.config
+            .health_check
+            .as_ref()
+            .map_or(CONNECT_TIMEOUT, |health_check| {
+                Duration::from_millis(health_check.timeout_ms)
+            })
+    }
+
+    /// The address to check for `backend`, if it can be checked.
+    fn health_check_address(&self, backend: &Backend) -> Option<SocketAddr> {
+        let health_check = self.config.health_check.as_ref()?;
+        match (self.config.protocol, health_check.port) {
+            (_, Some(port)) => Some(SocketAddr::new(backend.address.ip(), port)),
+            (ServiceProtocol::Tcp, None) => Some(backend.address),
+            (ServiceProtocol::Udp, None) => None,
+        }
+    }
+}
+
+struct Services {
+    services: HashMap<String, Service>,
+    /// Whether the health checker is running. It stops once no service has a health check.
+    checking_health: bool,
+}
+
+/// The services channels can be opened to, shared by every channel so connection counts and
+/// health are the same for all of them. Clones share the same services.
+#[derive(Clone)]
+pub struct ServiceRegistry(Arc<Mutex<Services>>);
+
+impl ServiceRegistry {
+    /// Starts health checks for the services that have them, until the last clone is dropped.
+    pub fn new(services: HashMap<String, ServiceConfig>) -> Self {
+        let registry = Self(Arc::new(Mutex::new(Services {
+            services: services
+                .into_iter()
+                .map(|(name, config)| (name, Service::new(config)))
+                .collect(),
+            checking_health: false,
+        })));
+        registry.start_health_checks();
+        registry
+    }
+
+    /// Starts the health checker if a service has a health check and it isn't running yet.
+    fn start_health_checks(&self) {
+        let mut services = self.0.lock().unwrap();
+        if services.checking_health
+            || !services
+                .services
+                .values()
+                .any(|service| service.config.health_check.is_some())
+        {
+            return;
+        }
+        services.checking_health = true;
+        let weak = Arc::downgrade(&self.0);
+        std::thread::spawn(move || check_health(weak));
+    }
+
+    /// Replaces the services, keeping the health and connection counts of backends that are
+    /// still there. Channels already open stay open.
+    pub fn update(&self, services: HashMap<String, ServiceConfig>) {
+        let mut services_guard = self.0.lock().unwrap();
+        let current = &mut services_guard.services;
+        let mut updated = HashMap::new();
+        for (name, config) in services {
+            let mut service = Service::new(config);
+            if let Some(previous) = current.remove(&name) {
+                for backend in &mut service.backends {
+                    if let Some(previous) = previous
+                        .backends
+                        .iter()
+                        .find(|previous| previous.address == backend.address)
+                    {
+                        backend.healthy = previous.healthy;
+                        backend.connections = previous.connections;
+                        backend.checked_at = previous.checked_at;
+                    }
+                }
+                service.next = previous.next;
+            }
+            updated.insert(name, service);
+        }
+        *current = updated;
+        drop(services_guard);
+        self.start_health_checks();
+    }
+
+    /// Reloads the services from the config file at `path` whenever it changes, until the last
+    /// clone is dropped. Only services are reloaded; the rest of the config needs a restart.
+    pub fn watch(&self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        let weak = Arc::downgrade(&self.0);
+        std::thread::spawn(move || {
+            let modified = || {
+                fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            };
+            let mut loaded: Option<SystemTime> = modified();
+            loop {
+                std::thread::sleep(CONFIG_POLL_INTERVAL);
+                let Some(registry) = weak.upgrade() else {
+                    break;
+                };
+                if modified() != loaded {
+                    loaded = modified();
+                    // A half-written file fails to parse, and is picked up once it's complete.
+                    match ServerConfig::load(&path) {
+                        Ok(config) => Self(registry).update(config.services),
+                        Err(_) => loaded = None,
+                    }
+                }
+            }
+        });
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.lock().unwrap().services.contains_key(name)
+    }
+
+    /// Opens a channel to a healthy backend of `name` with `connect`, trying the next one if it
+    /// fails. `connect` is given the service's socket options and how long connecting may take:
+    /// the health check's timeout if the service has one. The backend counts as connected until
+    /// the returned stream is dropped.
+    pub fn open(
+        &self,
+        name: &str,
+        mut connect: impl FnMut(
+            ServiceProtocol,
+            SocketAddr,
+            &SocketOptions,
+            Duration,
+        ) -> Result<Box<dyn ChannelStream>>,
+    ) -> Result<Box<dyn ChannelStream>> {
+        let (protocol, candidates, options, timeout) = self.candidates(name)?;
+        let mut last_error = anyhow!("Service {} has no healthy backends.", name);
+        for address in candidates {
+            match connect(protocol, address, &options, timeout) {
+                Ok(stream) => {
+                    self.add_connections(name, address, 1);
+                    return Ok(Box::new(ServiceChannelStream {
+                        stream,
+                        registry: self.clone(),
+                        name: name.to_owned(),
+                        address,
+                    }));
+                }
+                Err(err) => {
+                    self.connect_failed(name, address);
+                    last_error = err;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Healthy backends of `name` in the order its balancing says to try them, with the socket
+    /// options for them and how long connecting to one may take.
+    fn candidates(
+        &self,
+        name: &str,
+    ) -> Result<(ServiceProtocol, Vec<SocketAddr>, SocketOptions, Duration)> {
+        let mut services = self.0.lock().unwrap();
+        let Some(service) = services.services.get_mut(name) else {
+            bail!("Unknown service {}.", name);
+        };
+        let mut healthy = service
+            .backends
+            .iter()
+            .filter(|backend| backend.healthy)
+            .collect::<Vec<_>>();
+        match service.config.balancing {
+            Balancing::RoundRobin => {
+                if !healthy.is_empty() {
+                    healthy.rotate_left(service.next % healthy.len());
+                }
+            }
+            Balancing::LeastConnections => healthy.sort_by_key(|backend| backend.connections),
+        }
+        let candidates = healthy.iter().map(|backend| backend.address).collect();
+        service.next = service.next.wrapping_add(1);
+        Ok((
+            service.config.protocol,
+            candidates,
+            service.config.options.clone(),
+            service.connect_timeout(),
+        ))
+    }
+
+    fn add_connections(&self, name: &str, address: SocketAddr, delta: isize) {
+        let mut services = self.0.lock().unwrap();
+        if let Some(backend) = services.services.get_mut(name).and_then(|service| {
+            service
+                .backends
+                .iter_mut()
+                .find(|backend| backend.address == address)
+        }) {
+            backend.connections = backend.connections.saturating_add_signed(delta);
+        }
+    }
+
+    /// Takes a backend that couldn't be reached out of rotation until its next health check
+    /// passes. Backends without health checks stay in, since nothing would bring them back.
+    fn connect_failed(&self, name: &str, address: SocketAddr) {
+        let mut services = self.0.lock().unwrap();
+        let Some(service) = services.services.get_mut(name) else {
+            return;
+        };
+        if service.config.health_check.is_none() {
+            return;
+        }
+        if let Some(backend) = service
+            .backends
+            .iter_mut()
+            .find(|backend| backend.address == address)
+        {
+            backend.healthy = false;
+        }
+    }
+}
+
+fn check_health(registry: Weak<Mutex<Services>>) {
+    loop {
+        std::thread::sleep(HEALTH_CHECK_TICK);
+        let Some(registry) = registry.upgrade() else {
+            break;
+        };
+        let mut due = vec![];
+        {
+            let mut services = registry.lock().unwrap();
+            // Started again by an update that brings a health check back.
+            if !services
+                .services
+                .values()
+                .any(|service| service.config.health_check.is_some())
+            {
+                services.checking_health = false;
+                break;
+            }
+            for (name, service) in services.services.iter_mut() {
+                let Some(health_check) = service.config.health_check.clone() else {
+                    continue;
+                };
+                let interval = Duration::from_millis(health_check.interval_ms);
+                let timeout = Duration::from_millis(health_check.timeout_ms);
+                for index in 0..service.backends.len() {
+                    let backend = &service.backends[index];
+                    if backend
+                        .checked_at
+                        .is_some_and(|checked_at| checked_at.elapsed() < interval)
+                    {
+                        continue;
+                    }
+                    if let Some(check_address) = service.health_check_address(backend) {
+                        due.push((name.clone(), backend.address, check_address, timeout));
+                    }
+                    service.backends[index].checked_at = Some(Instant::now());
+                }
+            }
+        }
+        // Checks run in parallel and without the lock, so a slow backend holds up nothing.
+        let results = std::thread::scope(|scope| {
+            due.into_iter()
+                .map(|(name, address, check_address, timeout)| {
+                    scope.spawn(move || {
+                        let healthy = TcpStream::connect_timeout(&check_address, timeout).is_ok();
+                        (name, address, healthy)
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .filter_map(|check| check.join().ok())
+                .collect::<Vec<_>>()
+        });
+        let mut services = registry.lock().unwrap();
+        for (name, address, healthy) in results {
+            if let Some(backend) = services.services.get_mut(&name).and_then(|service| {
+                service
+                    .backends
+                    .iter_mut()
+                    .find(|backend| backend.address == address)
+            }) {
+                backend.healthy = healthy;
+            }
+        }
+    }
+}
+
+/// A channel to a service backend, which stops counting as a connection to it when dropped.
+struct ServiceChannelStream {
+    stream: Box<dyn ChannelStream>,
+    registry: ServiceRegistry,
+    name: String,
+    address: SocketAddr,
+}
+
+impl ChannelStream for ServiceChannelStream {
+    fn status(&mut self) -> Result<ChannelStatus> {
+        self.stream.status()
+    }
+
+    fn send(&mut self, data: Packet) -> Result<()> {
+        self.stream.send(data)
+    }
+
+    fn receive(&mut self) -> Result<Option<Packet>> {
+        self.stream.receive()
+    }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.stream.shutdown()
+    }
+}
+
+impl Drop for ServiceChannelStream {
+    fn drop(&mut self) {
+        self.registry.add_connections(&self.name, self.address, -1);
+    }
+}
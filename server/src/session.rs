@@ -0,0 +1,278 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use rusty_enet::{Packet, PacketKind};
+
+use crate::{FRAME_ACK, FRAME_SESSION};
+
+/// How often the server acknowledges what it received on a resumable tunnel, so the client can
+/// stop keeping it.
+const ACK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The secret a client resumes its tunnel with. Only the server and that client know it.
+pub(crate) type SessionToken = [u8; 16];
+
+/// Reliable packets on one channel id, counted in both directions.
+#[derive(Default)]
+struct Sequence {
+    /// Sent and not acknowledged yet, oldest first.
+    unacked: VecDeque<Vec<u8>>,
+    /// How many were acknowledged, which were all sent before the first of `unacked`.
+    acked: u32,
+    received: u32,
+    /// `received` as of the last acknowledgement sent.
+    received_acked: u32,
+}
+
+/// What a resumable tunnel needs to pick up where a lost transport left off: its token, and every
+/// reliable packet sent on it until the client acknowledges it, to be sent again after a resume.
+/// Session frames and unreliable packets aren't counted.
+pub(crate) struct Session {
+    pub(crate) token: SessionToken,
+    sequences: HashMap<u8, Sequence>,
+    unacked_bytes: usize,
+    max_unacked_bytes: usize,
+    acked_at: Instant,
+}
+
+fn counted(packet: &Packet) -> bool {
+    matches!(packet.kind(), PacketKind::Reliable) && packet.data().first() != Some(&FRAME_SESSION)
+}
+
+impl Session {
+    pub(crate) fn new(max_unacked_bytes: usize) -> Self {
+        Self {
+            token: rand::random(),
+            sequences: HashMap::new(),
+            unacked_bytes: 0,
+            max_unacked_bytes,
+            acked_at: Instant::now(),
+        }
+    }
+
+    /// Keeps `packet` until the client acknowledges it, if it's counted. Returns false once more
+    /// than the limit is kept, after which the tunnel can't be resumed.
+    pub(crate) fn sent(&mut self, channel_id: u8, packet: &Packet) -> bool {
+        if counted(packet) {
+            self.unacked_bytes += packet.data().len();
+            self.sequences
+                .entry(channel_id)
+                .or_default()
+                .unacked
+                .push_back(packet.data().to_vec());
+        }
+        self.unacked_bytes <= self.max_unacked_bytes
+    }
+
+    pub(crate) fn received(&mut self, channel_id: u8, packet: &Packet) {
+        if counted(packet) {
+            let sequence = self.sequences.entry(channel_id).or_default();
+            sequence.received = sequence.received.wrapping_add(1);
+        }
+    }
+
+    /// Stops keeping the packets on `channel_id` that the client has, given how many it received.
+    /// Returns false if that's more than were sent, or fewer than it acknowledged before.
+    pub(crate) fn acknowledge(&mut self, channel_id: u8, received: u32) -> bool {
+        let sequence = self.sequences.entry(channel_id).or_default();
+        let acked = received.wrapping_sub(sequence.acked) as usize;
+        if acked > sequence.unacked.len() {
+            return false;
+        }
+        for data in sequence.unacked.drain(..acked) {
+            self.unacked_bytes -= data.len();
+        }
+        sequence.acked = received;
+        true
+    }
+
+    /// Takes what the client says it received after a resume, as [`encode_counts`] made it, and
+    /// returns the packets it's missing. Channels it leaves out count as nothing received. None if
+    /// the counts don't fit what was sent.
+    pub(crate) fn resume(&mut self, counts: &[u8]) -> Option<Vec<(u8, Packet)>> {
+        let counts = decode_counts(counts)?;
+        let channel_ids = self
+            .sequences
+            .keys()
+            .copied()
+            .chain(counts.keys().copied())
+            .collect::<Vec<_>>();
+        for channel_id in channel_ids {
+            if !self.acknowledge(channel_id, counts.get(&channel_id).copied().unwrap_or(0)) {
+                return None;
+            }
+        }
+        Some(
+            self.sequences
+                .iter()
+                .flat_map(|(channel_id, sequence)| {
+                    sequence
+                        .unacked
+                        .iter()
+                        .map(move |data| (*channel_id, Packet::reliable(data)))
+                })
+                .collect(),
+        )
+    }
+
+    /// How many packets were received on each channel, as [`encode_counts`] makes it.
+    pub(crate) fn received_counts(&self) -> Vec<u8> {
+        encode_counts(
+            self.sequences
+                .iter()
+                .map(|(channel_id, sequence)| (*channel_id, sequence.received)),
+        )
+    }
+
+    /// Acknowledgements for what was received since the last ones, at most every
+    /// [`ACK_INTERVAL`]. They're unreliable, since a later one supersedes a lost one.
+    pub(crate) fn acks(&mut self) -> Vec<(u8, Packet)> {
+        if self.acked_at.elapsed() < ACK_INTERVAL {
+            return vec![];
+        }
+        self.acked_at = Instant::now();
+        self.sequences
+            .iter_mut()
+            .filter(|(_, sequence)| sequence.received != sequence.received_acked)
+            .map(|(channel_id, sequence)| {
+                sequence.received_acked = sequence.received;
+                let mut data = vec![FRAME_ACK];
+                data.extend(sequence.received.to_be_bytes());
+                (*channel_id, Packet::unreliable(&data))
+            })
+            .collect()
+    }
+}
+
+/// A channel id and a big-endian count for each channel.
+fn encode_counts(counts: impl Iterator<Item = (u8, u32)>) -> Vec<u8> {
+    let mut data = vec![];
+    for (channel_id, count) in counts {
+        data.push(channel_id);
+        data.extend(count.to_be_bytes());
+    }
+    data
+}
+
+fn decode_counts(data: &[u8]) -> Option<HashMap<u8, u32>> {
+    if !data.len().is_multiple_of(5) {
+        return None;
+    }
+    Some(
+        data.chunks(5)
+            .map(|chunk| {
+                (
+                    chunk[0],
+                    u32::from_be_bytes([chunk[1], chunk[2], chunk[3], chunk[4]]),
+                )
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(counts: &[(u8, u32)]) -> Vec<u8> {
+        encode_counts(counts.iter().copied())
+    }
+
+    #[test]
+    fn acknowledged_packets_are_no_longer_kept() {
+        let mut session = Session::new(1024);
+        for data in [b"a", b"b", b"c"] {
+            assert!(session.sent(1, &Packet::reliable(data)));
+        }
+        assert!(session.acknowledge(1, 2));
+        assert_eq!(session.unacked_bytes, 1);
+        let missing = session.resume(&counts(&[(1, 2)])).unwrap();
+        assert_eq!(missing.len(), 1);
+        assert_eq!(missing[0].0, 1);
+        assert_eq!(missing[0].1.data(), b"c");
+    }
+
+    #[test]
+    fn acknowledging_more_than_was_sent_or_going_back_fails() {
+        let mut session = Session::new(1024);
+        session.sent(1, &Packet::reliable(b"a"));
+        session.sent(1, &Packet::reliable(b"b"));
+        assert!(!session.acknowledge(1, 3));
+        assert!(session.acknowledge(1, 2));
+        assert!(!session.acknowledge(1, 1));
+        assert!(session.acknowledge(1, 2));
+    }
+
+    #[test]
+    fn session_frames_and_unreliable_packets_are_not_counted() {
+        let mut session = Session::new(1024);
+        session.sent(1, &Packet::reliable(&[FRAME_SESSION, 1, 2]));
+        session.sent(1, &Packet::unreliable(b"a"));
+        session.received(1, &Packet::reliable(&[FRAME_SESSION]));
+        session.received(1, &Packet::unreliable(b"a"));
+        assert_eq!(session.unacked_bytes, 0);
+        assert!(session.resume(&[]).unwrap().is_empty());
+        assert!(session.received_counts().is_empty());
+    }
+
+    #[test]
+    fn keeping_more_than_the_limit_fails() {
+        let mut session = Session::new(4);
+        assert!(session.sent(1, &Packet::reliable(b"ab")));
+        assert!(session.sent(2, &Packet::reliable(b"cd")));
+        assert!(!session.sent(1, &Packet::reliable(b"e")));
+    }
+
+    #[test]
+    fn resume_sends_everything_missing_on_every_channel() {
+        let mut session = Session::new(1024);
+        session.sent(1, &Packet::reliable(b"a"));
+        session.sent(1, &Packet::reliable(b"b"));
+        session.sent(2, &Packet::reliable(b"c"));
+        // Channel 2 is left out, so counts as nothing received.
+        let mut missing = session.resume(&counts(&[(1, 1)])).unwrap();
+        missing.sort_by_key(|(channel_id, _)| *channel_id);
+        let missing = missing
+            .iter()
+            .map(|(channel_id, packet)| (*channel_id, packet.data().to_vec()))
+            .collect::<Vec<_>>();
+        assert_eq!(missing, [(1, b"b".to_vec()), (2, b"c".to_vec())]);
+    }
+
+    #[test]
+    fn resume_fails_on_counts_that_dont_fit() {
+        let mut session = Session::new(1024);
+        session.sent(1, &Packet::reliable(b"a"));
+        assert!(session.resume(&[1, 0, 0]).is_none());
+        assert!(session.resume(&counts(&[(1, 2)])).is_none());
+        assert!(session.resume(&counts(&[(3, 1)])).is_none());
+    }
+
+    #[test]
+    fn received_counts_round_trip() {
+        let mut session = Session::new(1024);
+        session.received(1, &Packet::reliable(b"a"));
+        session.received(1, &Packet::reliable(b"b"));
+        session.received(7, &Packet::reliable(b"c"));
+        assert_eq!(
+            decode_counts(&session.received_counts()).unwrap(),
+            HashMap::from([(1, 2), (7, 1)])
+        );
+    }
+
+    #[test]
+    fn acks_go_out_once_per_interval_for_new_packets() {
+        let mut session = Session::new(1024);
+        session.received(1, &Packet::reliable(b"a"));
+        assert!(session.acks().is_empty());
+        session.acked_at = Instant::now() - ACK_INTERVAL;
+        let acks = session.acks();
+        assert_eq!(acks.len(), 1);
+        assert_eq!(acks[0].0, 1);
+        assert_eq!(acks[0].1.data(), [FRAME_ACK, 0, 0, 0, 1]);
+        session.acked_at = Instant::now() - ACK_INTERVAL;
+        assert!(session.acks().is_empty());
+    }
+}
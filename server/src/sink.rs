@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use rusty_enet::Packet;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{ChannelStatus, ChannelStream};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SinkConfig {
+    /// How often the received totals are reported back.
+    pub report_interval_ms: u64,
+}
+
+impl Default for SinkConfig {
+    fn default() -> Self {
+        Self {
+            report_interval_ms: 1000,
+        }
+    }
+}
+
+/// Bounds on the [`SinkConfig`] clients ask for, so reports can't flood the tunnel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SinkLimits {
+    pub min_report_interval_ms: u64,
+}
+
+impl Default for SinkLimits {
+    fn default() -> Self {
+        Self {
+            min_report_interval_ms: 100,
+        }
+    }
+}
+
+impl SinkConfig {
+    pub fn clamp(&self, limits: &SinkLimits) -> Self {
+        Self {
+            // Never zero, which would report on every poll.
+            report_interval_ms: self
+                .report_interval_ms
+                .max(limits.min_report_interval_ms.max(1)),
+        }
+    }
+}
+
+/// Drops everything it receives, periodically replying with a JSON report of the totals so far.
+pub struct SinkChannelStream {
+    config: SinkConfig,
+    instant: Instant,
+    last_report: Instant,
+    bytes: u64,
+    packets: u64,
+}
+
+impl SinkChannelStream {
+    /// `config` should already be clamped to the server's [`SinkLimits`].
+    pub fn new(config: SinkConfig) -> Self {
+        Self {
+            config,
+            instant: Instant::now(),
+            last_report: Instant::now(),
+            bytes: 0,
+            packets: 0,
+        }
+    }
+}
+
+impl ChannelStream for SinkChannelStream {
+    fn status(&mut self) -> Result<ChannelStatus> {
+        Ok(ChannelStatus::Connected)
+    }
+
+    fn send(&mut self, packet: Packet) -> Result<()> {
+        self.bytes += packet.data().len() as u64;
+        self.packets += 1;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Packet>> {
+        if self.last_report.elapsed() < Duration::from_millis(self.config.report_interval_ms) {
+            return Ok(None);
+        }
+        self.last_report = Instant::now();
+        let report = json!({
+            "bytes": self.bytes,
+            "packets": self.packets,
+            "elapsed_ms": self.instant.elapsed().as_millis() as u64,
+        });
+        Ok(Some(Packet::reliable(report.to_string().as_bytes())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sink_report_interval_is_clamped() {
+        let limits = SinkLimits::default();
+        let config = SinkConfig {
+            report_interval_ms: 0,
+        };
+        assert_eq!(
+            config.clamp(&limits).report_interval_ms,
+            limits.min_report_interval_ms
+        );
+        let limits = SinkLimits {
+            min_report_interval_ms: 0,
+        };
+        assert_eq!(config.clamp(&limits).report_interval_ms, 1);
+    }
+}
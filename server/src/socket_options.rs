@@ -0,0 +1,115 @@
+use std::{
+    io::{self, ErrorKind},
+    net::SocketAddr,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use socket2::{Socket, TcpKeepalive};
+
+pub use webrtc_proxy_shared::{SocketLimits, SocketOptions};
+
+/// Sets `options` on a socket before it connects to `address`, so buffer sizes apply to the TCP
+/// handshake too. Fails if TOS is asked for toward an IPv6 address, which has no TOS byte.
+pub(crate) fn apply_socket_options(
+    options: &SocketOptions,
+    socket: &Socket,
+    address: SocketAddr,
+    tcp: bool,
+) -> io::Result<()> {
+    if tcp {
+        if let Some(nodelay) = options.nodelay {
+            socket.set_nodelay(nodelay)?;
+        }
+        if let Some(keepalive_ms) = options.keepalive_ms {
+            let keepalive = Duration::from_millis(keepalive_ms);
+            let keepalive = TcpKeepalive::new().with_time(keepalive);
+            #[cfg(any(target_os = "linux", target_os = "macos", windows))]
+            let keepalive = keepalive.with_interval(Duration::from_millis(keepalive_ms));
+            socket.set_tcp_keepalive(&keepalive)?;
+        }
+    }
+    if let Some(size) = options.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+    }
+    if let Some(size) = options.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+    }
+    if let Some(ttl) = options.ttl {
+        if address.is_ipv4() {
+            socket.set_ttl(ttl)?;
+        } else {
+            socket.set_unicast_hops_v6(ttl)?;
+        }
+    }
+    if let Some(tos) = options.tos {
+        if !address.is_ipv4() {
+            return Err(io::Error::new(
+                ErrorKind::Unsupported,
+                "TOS can't be set toward an IPv6 address.",
+            ));
+        }
+        socket.set_tos(tos)?;
+    }
+    Ok(())
+}
+
+/// Where a TCP or UDP channel connects, with its socket options. Deserializes from a bare address
+/// too, as `{"Tcp": "1.2.3.4:80"}` was before there were options, and serializes to one when
+/// there are none.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "SocketTargetRepr", into = "SocketTargetRepr")]
+pub struct SocketTarget {
+    /// `host:port`, where the host is a name or an IP address, with brackets for IPv6. Names are
+    /// resolved by the server, through its destination policy.
+    pub address: String,
+    pub options: SocketOptions,
+}
+
+impl From<SocketAddr> for SocketTarget {
+    fn from(address: SocketAddr) -> Self {
+        address.to_string().into()
+    }
+}
+
+impl From<String> for SocketTarget {
+    fn from(address: String) -> Self {
+        Self {
+            address,
+            options: SocketOptions::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SocketTargetRepr {
+    Address(String),
+    Target {
+        address: String,
+        #[serde(default)]
+        options: SocketOptions,
+    },
+}
+
+impl From<SocketTargetRepr> for SocketTarget {
+    fn from(repr: SocketTargetRepr) -> Self {
+        match repr {
+            SocketTargetRepr::Address(address) => address.into(),
+            SocketTargetRepr::Target { address, options } => Self { address, options },
+        }
+    }
+}
+
+impl From<SocketTarget> for SocketTargetRepr {
+    fn from(target: SocketTarget) -> Self {
+        if target.options == SocketOptions::default() {
+            Self::Address(target.address)
+        } else {
+            Self::Target {
+                address: target.address,
+                options: target.options,
+            }
+        }
+    }
+}
@@ -1,30 +1,83 @@
 use std::{
     io::{ErrorKind, Read, Write},
-    net::{SocketAddr, TcpStream},
+    net::{Shutdown, SocketAddr, TcpStream},
+    time::{Duration, Instant},
 };
 
 use anyhow::{bail, Result};
 use rusty_enet::Packet;
+use socket2::{Domain, Protocol, Socket, Type};
 
-use crate::{ChannelStatus, ChannelStream};
+use crate::{apply_socket_options, ChannelStatus, ChannelStream, SocketOptions};
 
-pub struct TcpChannelStream(TcpStream);
+pub struct TcpChannelStream {
+    stream: TcpStream,
+    eof: bool,
+    shut_down: bool,
+    /// When the client last sent something, or the target reached EOF if that was later.
+    active_at: Instant,
+    eof_idle_timeout: Option<Duration>,
+}
 
 impl TcpChannelStream {
-    pub fn new(address: SocketAddr) -> Result<Self> {
-        let stream = TcpStream::connect(address)?;
+    /// Connects to `address`, giving up after `timeout` if there is one, and sends `header`
+    /// first if there is one. With an `eof_idle_timeout`, the channel closes once the target
+    /// reached EOF and the client sent nothing for that long.
+    pub fn new(
+        address: SocketAddr,
+        header: Option<&[u8]>,
+        options: &SocketOptions,
+        timeout: Option<Duration>,
+        eof_idle_timeout: Option<Duration>,
+    ) -> Result<Self> {
+        let socket = Socket::new(
+            Domain::for_address(address),
+            Type::STREAM,
+            Some(Protocol::TCP),
+        )?;
+        apply_socket_options(options, &socket, address, true)?;
+        match timeout {
+            Some(timeout) => socket.connect_timeout(&address.into(), timeout)?,
+            None => socket.connect(&address.into())?,
+        }
+        let mut stream = TcpStream::from(socket);
+        if let Some(header) = header {
+            stream.write_all(header)?;
+        }
         stream.set_nonblocking(true)?;
-        Ok(Self(stream))
+        Ok(Self {
+            stream,
+            eof: false,
+            shut_down: false,
+            active_at: Instant::now(),
+            eof_idle_timeout,
+        })
     }
 }
 
 impl ChannelStream for TcpChannelStream {
     fn status(&mut self) -> Result<ChannelStatus> {
-        Ok(ChannelStatus::Connected)
+        Ok(match (self.eof, self.shut_down) {
+            (false, _) => ChannelStatus::Connected,
+            (true, false)
+                if self
+                    .eof_idle_timeout
+                    .is_some_and(|timeout| self.active_at.elapsed() > timeout) =>
+            {
+                ChannelStatus::Disconnected
+            }
+            (true, false) => ChannelStatus::Eof,
+            // Nothing can flow either way anymore.
+            (true, true) => ChannelStatus::Disconnected,
+        })
     }
 
     fn send(&mut self, packet: Packet) -> Result<()> {
-        if self.0.write(packet.data())? == packet.data().len() {
+        if self.shut_down {
+            bail!("Write side shut down.");
+        }
+        self.active_at = Instant::now();
+        if self.stream.write(packet.data())? == packet.data().len() {
             Ok(())
         } else {
             bail!("Packet too large.");
@@ -33,12 +86,24 @@ impl ChannelStream for TcpChannelStream {
 
     fn receive(&mut self) -> Result<Option<Packet>> {
         let mut buffer = [0; 4096];
-        match self.0.read(&mut buffer) {
-            Ok(received) if received == 0 => bail!("Disconnected."),
+        match self.stream.read(&mut buffer) {
+            Ok(received) if received == 0 => {
+                if !self.eof {
+                    self.eof = true;
+                    self.active_at = Instant::now();
+                }
+                Ok(None)
+            }
             Ok(received) if received == 4096 => bail!("Packet too large."),
             Ok(received) => Ok(Some(Packet::reliable(&buffer[0..received]))),
             Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(None),
             Err(err) => Err(err.into()),
         }
     }
+
+    fn shutdown(&mut self) -> Result<()> {
+        self.stream.shutdown(Shutdown::Write)?;
+        self.shut_down = true;
+        Ok(())
+    }
 }
@@ -0,0 +1,242 @@
+use std::{
+    fs::File,
+    io::{BufReader, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpStream},
+    path::Path,
+    sync::{Arc, OnceLock},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, ClientConfig, ClientConnection, OwnedTrustAnchor, PrivateKey, RootCertStore,
+    ServerName,
+};
+use rusty_enet::Packet;
+
+use crate::{ChannelStatus, ChannelStream, DestinationPolicy, TlsSettings};
+
+pub struct TlsChannelStream {
+    connection: ClientConnection,
+    stream: TcpStream,
+    eof: bool,
+    closed: bool,
+}
+
+impl TlsChannelStream {
+    pub fn new(
+        address: &str,
+        sni: Option<&str>,
+        alpn: &[String],
+        verify: bool,
+        settings: &TlsSettings,
+        policy: &DestinationPolicy,
+        client_address: Option<SocketAddr>,
+    ) -> Result<Self> {
+        let Some((host, port)) = address
+            .rsplit_once(':')
+            .and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+        else {
+            bail!("Invalid address {}.", address);
+        };
+        let addresses = policy.resolve(host, port)?;
+        if !verify && !settings.allow_insecure {
+            bail!("Certificate verification can't be disabled.");
+        }
+
+        let mut roots = RootCertStore::empty();
+        if settings.system_roots {
+            for certificate in native_roots()? {
+                // Some systems ship certificates rustls can't parse, which shouldn't stop the
+                // rest from loading.
+                _ = roots.add(certificate);
+            }
+        } else if settings.ca_files.is_empty() {
+            roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|anchor| {
+                OwnedTrustAnchor::from_subject_spki_name_constraints(
+                    anchor.subject,
+                    anchor.spki,
+                    anchor.name_constraints,
+                )
+            }));
+        }
+        for path in &settings.ca_files {
+            for certificate in load_certificates(path)? {
+                roots.add(&certificate)?;
+            }
+        }
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let mut config = match settings.client_certificate(host) {
+            Some(client) => builder.with_client_auth_cert(
+                load_certificates(&client.certificate)?,
+                load_key(&client.key)?,
+            )?,
+            None => builder.with_no_client_auth(),
+        };
+        config.alpn_protocols = alpn
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect();
+        if !verify {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(NoCertificateVerification));
+        }
+
+        let server_name = ServerName::try_from(
+            sni.unwrap_or(host.trim_start_matches('[').trim_end_matches(']')),
+        )?;
+        let mut connection = ClientConnection::new(Arc::new(config), server_name)?;
+        let timeout = Duration::from_millis(settings.timeout_ms);
+        let mut stream = connect(&addresses, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        if let Some(version) = policy.proxy_protocol(host, port) {
+            stream.write_all(&version.header(client_address, stream.peer_addr()?, false))?;
+        }
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream)?;
+        }
+        stream.set_nonblocking(true)?;
+        Ok(Self {
+            connection,
+            stream,
+            eof: false,
+            closed: false,
+        })
+    }
+
+    fn write_tls(&mut self) -> Result<()> {
+        while self.connection.wants_write() {
+            match self.connection.write_tls(&mut self.stream) {
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChannelStream for TlsChannelStream {
+    fn status(&mut self) -> Result<ChannelStatus> {
+        if self.closed {
+            Ok(ChannelStatus::Disconnected)
+        } else {
+            Ok(ChannelStatus::Connected)
+        }
+    }
+
+    fn send(&mut self, packet: Packet) -> Result<()> {
+        self.connection.writer().write_all(packet.data())?;
+        self.write_tls()
+    }
+
+    fn receive(&mut self) -> Result<Option<Packet>> {
+        self.write_tls()?;
+        if !self.eof {
+            match self.connection.read_tls(&mut self.stream) {
+                Ok(0) => self.eof = true,
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+                Err(err) => return Err(err.into()),
+            }
+            self.connection.process_new_packets()?;
+            self.write_tls()?;
+        }
+        let mut buffer = [0; 4096];
+        match self.connection.reader().read(&mut buffer) {
+            Ok(0) => {
+                self.closed = true;
+                Ok(None)
+            }
+            Ok(received) => Ok(Some(Packet::reliable(&buffer[0..received]))),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                self.closed = self.eof;
+                Ok(None)
+            }
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => {
+                self.closed = true;
+                Ok(None)
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// Connects to the first of `addresses` that answers within `timeout`.
+pub(crate) fn connect(addresses: &[SocketAddr], timeout: Duration) -> Result<TcpStream> {
+    let mut last_error = None;
+    for address in addresses {
+        match TcpStream::connect_timeout(address, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_error = Some(err),
+        }
+    }
+    match last_error {
+        Some(err) => Err(err.into()),
+        None => bail!("No addresses to connect to."),
+    }
+}
+
+/// The operating system's root certificates, loaded once since reading them is slow.
+fn native_roots() -> Result<&'static [Certificate]> {
+    static ROOTS: OnceLock<Result<Vec<Certificate>, String>> = OnceLock::new();
+    ROOTS
+        .get_or_init(|| {
+            rustls_native_certs::load_native_certs()
+                .map(|certificates| {
+                    certificates
+                        .into_iter()
+                        .map(|certificate| Certificate(certificate.0))
+                        .collect()
+                })
+                .map_err(|err| err.to_string())
+        })
+        .as_deref()
+        .map_err(|err| anyhow!("Could not load system root certificates: {}", err))
+}
+
+struct NoCertificateVerification;
+
+impl ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+fn load_certificates(path: &Path) -> Result<Vec<Certificate>> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("could not open {}", path.display()))?,
+    );
+    Ok(rustls_pemfile::certs(&mut reader)?
+        .into_iter()
+        .map(Certificate)
+        .collect())
+}
+
+fn load_key(path: &Path) -> Result<PrivateKey> {
+    let mut reader = BufReader::new(
+        File::open(path).with_context(|| format!("could not open {}", path.display()))?,
+    );
+    loop {
+        match rustls_pemfile::read_one(&mut reader)? {
+            Some(rustls_pemfile::Item::RSAKey(key))
+            | Some(rustls_pemfile::Item::PKCS8Key(key))
+            | Some(rustls_pemfile::Item::ECKey(key)) => return Ok(PrivateKey(key)),
+            Some(_) => {}
+            None => bail!("No private key in {}.", path.display()),
+        }
+    }
+}
@@ -5,26 +5,40 @@ use std::{
 
 use anyhow::{bail, Result};
 use rusty_enet::Packet;
+use socket2::{Domain, Protocol, Socket, Type};
 
-use crate::{ChannelStatus, ChannelStream};
+use crate::{apply_socket_options, ChannelStatus, ChannelStream, SocketOptions};
 
-pub struct UdpChannelStream(UdpSocket);
+pub struct UdpChannelStream {
+    socket: UdpSocket,
+    /// Sent at the start of every datagram, e.g. a PROXY protocol header. It's made once when the
+    /// channel opens, like the header of a TCP channel, so after a resume from another address it
+    /// still names the address the client had then.
+    header: Option<Vec<u8>>,
+}
 
 impl UdpChannelStream {
-    pub fn new(address: SocketAddr) -> Result<Self> {
-        let socket = if address.is_ipv4() {
-            UdpSocket::bind(SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)))
+    pub fn new(
+        address: SocketAddr,
+        header: Option<Vec<u8>>,
+        options: &SocketOptions,
+    ) -> Result<Self> {
+        let socket = Socket::new(
+            Domain::for_address(address),
+            Type::DGRAM,
+            Some(Protocol::UDP),
+        )?;
+        apply_socket_options(options, &socket, address, false)?;
+        let unspecified = if address.is_ipv4() {
+            SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))
         } else {
-            UdpSocket::bind(SocketAddr::V6(SocketAddrV6::new(
-                Ipv6Addr::UNSPECIFIED,
-                0,
-                0,
-                0,
-            )))
-        }?;
+            SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0))
+        };
+        socket.bind(&unspecified.into())?;
+        let socket = UdpSocket::from(socket);
         socket.connect(address)?;
         socket.set_nonblocking(true)?;
-        Ok(Self(socket))
+        Ok(Self { socket, header })
     }
 }
 
@@ -34,7 +48,11 @@ impl ChannelStream for UdpChannelStream {
     }
 
     fn send(&mut self, packet: Packet) -> Result<()> {
-        if self.0.send(packet.data())? == packet.data().len() {
+        let datagram = match &self.header {
+            Some(header) => [header.as_slice(), packet.data()].concat(),
+            None => packet.data().to_vec(),
+        };
+        if self.socket.send(&datagram)? == datagram.len() {
             Ok(())
         } else {
             bail!("Packet too large.");
@@ -43,7 +61,7 @@ impl ChannelStream for UdpChannelStream {
 
     fn receive(&mut self) -> Result<Option<Packet>> {
         let mut buffer = [0; 4096];
-        match self.0.recv(&mut buffer) {
+        match self.socket.recv(&mut buffer) {
             Ok(received) if received == 0 => bail!("Disconnected."),
             Ok(received) if received == 4096 => bail!("Packet too large."),
             Ok(received) => {
@@ -0,0 +1,148 @@
+use std::{borrow::Cow, io::ErrorKind, net::TcpStream, str, time::Duration};
+
+use anyhow::{anyhow, bail, Result};
+use rusty_enet::Packet;
+use tungstenite::{
+    client::IntoClientRequest,
+    http::{HeaderName, HeaderValue},
+    protocol::{frame::coding::CloseCode, CloseFrame},
+    stream::MaybeTlsStream,
+    Error, Message, WebSocket,
+};
+
+use crate::{tls::connect, ChannelStatus, ChannelStream, DestinationPolicy};
+
+pub const WEBSOCKET_TEXT: u8 = 0;
+pub const WEBSOCKET_BINARY: u8 = 1;
+pub const WEBSOCKET_CLOSE: u8 = 2;
+
+pub struct WebSocketChannelStream {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    closed: bool,
+}
+
+impl WebSocketChannelStream {
+    /// Connects to `url` and completes the handshake, each within `timeout`, so a target that
+    /// accepts but never answers can't hold the channel forever.
+    pub fn new(
+        url: &str,
+        headers: &[(String, String)],
+        subprotocols: &[String],
+        policy: &DestinationPolicy,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let mut request = url.into_client_request()?;
+        for (name, value) in headers {
+            request.headers_mut().append(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            );
+        }
+        if !subprotocols.is_empty() {
+            request.headers_mut().insert(
+                "Sec-WebSocket-Protocol",
+                HeaderValue::from_str(&subprotocols.join(", "))?,
+            );
+        }
+        let Some(host) = request.uri().host() else {
+            bail!("Invalid WebSocket URL.");
+        };
+        let port = match (request.uri().port_u16(), request.uri().scheme_str()) {
+            (Some(port), _) => port,
+            (None, Some("wss")) => 443,
+            (None, _) => 80,
+        };
+        let addresses = policy.resolve(host, port)?;
+        let stream = connect(&addresses, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        let (mut socket, _) = tungstenite::client_tls(request, stream)
+            .map_err(|err| anyhow!("WebSocket handshake failed: {}", err))?;
+        match socket.get_mut() {
+            MaybeTlsStream::Plain(stream) => stream.set_nonblocking(true)?,
+            MaybeTlsStream::Rustls(stream) => stream.get_mut().set_nonblocking(true)?,
+            _ => bail!("Unsupported stream."),
+        }
+        Ok(Self {
+            socket,
+            closed: false,
+        })
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match self.socket.flush() {
+            Ok(()) => Ok(()),
+            Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => Ok(()),
+            Err(Error::ConnectionClosed) | Err(Error::AlreadyClosed) => {
+                self.closed = true;
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl ChannelStream for WebSocketChannelStream {
+    fn status(&mut self) -> Result<ChannelStatus> {
+        if self.closed {
+            Ok(ChannelStatus::Disconnected)
+        } else {
+            Ok(ChannelStatus::Connected)
+        }
+    }
+
+    fn send(&mut self, packet: Packet) -> Result<()> {
+        let message = match packet.data().split_first() {
+            Some((&WEBSOCKET_TEXT, data)) => Message::Text(str::from_utf8(data)?.to_owned()),
+            Some((&WEBSOCKET_BINARY, data)) => Message::Binary(data.to_vec()),
+            Some((&WEBSOCKET_CLOSE, data)) if data.len() >= 2 => Message::Close(Some(CloseFrame {
+                code: CloseCode::from(u16::from_be_bytes([data[0], data[1]])),
+                reason: Cow::Owned(str::from_utf8(&data[2..])?.to_owned()),
+            })),
+            Some((&WEBSOCKET_CLOSE, _)) => Message::Close(None),
+            _ => bail!("Invalid WebSocket message."),
+        };
+        match self.socket.write(message) {
+            Ok(()) => {}
+            Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err.into()),
+        }
+        self.flush()
+    }
+
+    fn receive(&mut self) -> Result<Option<Packet>> {
+        loop {
+            let data = match self.socket.read() {
+                Ok(Message::Text(text)) => {
+                    let mut data = vec![WEBSOCKET_TEXT];
+                    data.extend(text.as_bytes());
+                    data
+                }
+                Ok(Message::Binary(binary)) => {
+                    let mut data = vec![WEBSOCKET_BINARY];
+                    data.extend(binary);
+                    data
+                }
+                Ok(Message::Close(frame)) => {
+                    let mut data = vec![WEBSOCKET_CLOSE];
+                    if let Some(frame) = frame {
+                        data.extend(u16::from(frame.code).to_be_bytes());
+                        data.extend(frame.reason.as_bytes());
+                    }
+                    data
+                }
+                Ok(Message::Ping(_)) | Ok(Message::Pong(_)) | Ok(Message::Frame(_)) => {
+                    self.flush()?;
+                    continue;
+                }
+                Err(Error::Io(err)) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
+                Err(Error::ConnectionClosed) | Err(Error::AlreadyClosed) => {
+                    self.closed = true;
+                    return Ok(None);
+                }
+                Err(err) => return Err(err.into()),
+            };
+            return Ok(Some(Packet::reliable(&data)));
+        }
+    }
+}
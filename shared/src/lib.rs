@@ -0,0 +1,5 @@
+//! What `webrtc_proxy_client` and `webrtc_proxy_server` have in common.
+
+mod socket_options;
+
+pub use socket_options::*;
@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+/// Linux takes keepalive times in whole seconds and refuses zero, so shorter ones can't be set.
+const MIN_KEEPALIVE_MS: u64 = 1000;
+
+/// Socket options for the proxy's socket to the target of a TCP or UDP channel. Unset options
+/// keep the OS defaults, and the server clamps the rest to its [`SocketLimits`].
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SocketOptions {
+    /// Disable Nagle's algorithm. TCP only.
+    pub nodelay: Option<bool>,
+    /// Send keepalive probes after this long idle, and this often after that. TCP only.
+    pub keepalive_ms: Option<u64>,
+    pub send_buffer_size: Option<usize>,
+    pub recv_buffer_size: Option<usize>,
+    /// IP TTL, or the hop limit for IPv6.
+    pub ttl: Option<u32>,
+    /// IP TOS byte, DSCP in the upper six bits. IPv4 only; the server refuses it toward an IPv6
+    /// address.
+    pub tos: Option<u32>,
+}
+
+/// Bounds on the [`SocketOptions`] clients ask for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SocketLimits {
+    /// Shortest keepalive time, which is never less than a second.
+    pub min_keepalive_ms: u64,
+    pub max_buffer_size: usize,
+    pub min_ttl: u32,
+    pub max_ttl: u32,
+    /// Let clients set the TOS byte. Without it, their TOS is ignored.
+    pub allow_tos: bool,
+}
+
+impl Default for SocketLimits {
+    fn default() -> Self {
+        Self {
+            min_keepalive_ms: MIN_KEEPALIVE_MS,
+            max_buffer_size: 4 * 1024 * 1024,
+            min_ttl: 1,
+            max_ttl: 255,
+            allow_tos: false,
+        }
+    }
+}
+
+impl SocketOptions {
+    pub fn clamp(&self, limits: &SocketLimits) -> Self {
+        Self {
+            nodelay: self.nodelay,
+            keepalive_ms: self.keepalive_ms.map(|keepalive_ms| {
+                keepalive_ms.max(limits.min_keepalive_ms.max(MIN_KEEPALIVE_MS))
+            }),
+            send_buffer_size: self
+                .send_buffer_size
+                .map(|size| size.min(limits.max_buffer_size)),
+            recv_buffer_size: self
+                .recv_buffer_size
+                .map(|size| size.min(limits.max_buffer_size)),
+            ttl: self
+                .ttl
+                .map(|ttl| ttl.clamp(limits.min_ttl, limits.max_ttl.max(limits.min_ttl))),
+            tos: self
+                .tos
+                .filter(|_| limits.allow_tos)
+                .map(|tos| tos.min(u8::MAX as u32)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> SocketLimits {
+        SocketLimits {
+            min_keepalive_ms: 5000,
+            max_buffer_size: 65536,
+            min_ttl: 2,
+            max_ttl: 64,
+            allow_tos: true,
+        }
+    }
+
+    #[test]
+    fn values_within_limits_are_kept() {
+        let options = SocketOptions {
+            nodelay: Some(true),
+            keepalive_ms: Some(5000),
+            send_buffer_size: Some(65536),
+            recv_buffer_size: Some(1024),
+            ttl: Some(64),
+            tos: Some(255),
+        };
+        assert_eq!(options.clamp(&limits()), options);
+        let options = SocketOptions {
+            ttl: Some(2),
+            tos: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(options.clamp(&limits()), options);
+    }
+
+    #[test]
+    fn values_past_limits_are_clamped() {
+        let options = SocketOptions {
+            keepalive_ms: Some(4999),
+            send_buffer_size: Some(65537),
+            recv_buffer_size: Some(usize::MAX),
+            ttl: Some(1),
+            tos: Some(256),
+            ..Default::default()
+        }
+        .clamp(&limits());
+        assert_eq!(options.keepalive_ms, Some(5000));
+        assert_eq!(options.send_buffer_size, Some(65536));
+        assert_eq!(options.recv_buffer_size, Some(65536));
+        assert_eq!(options.ttl, Some(2));
+        assert_eq!(options.tos, Some(255));
+        let options = SocketOptions {
+            ttl: Some(u32::MAX),
+            ..Default::default()
+        };
+        assert_eq!(options.clamp(&limits()).ttl, Some(64));
+    }
+
+    #[test]
+    fn keepalive_is_never_under_a_second() {
+        let limits = SocketLimits {
+            min_keepalive_ms: 0,
+            ..limits()
+        };
+        let options = SocketOptions {
+            keepalive_ms: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(options.clamp(&limits).keepalive_ms, Some(1000));
+    }
+
+    #[test]
+    fn tos_is_dropped_unless_allowed() {
+        let options = SocketOptions {
+            tos: Some(0x10),
+            ..Default::default()
+        };
+        assert_eq!(options.clamp(&SocketLimits::default()).tos, None);
+        assert_eq!(options.clamp(&limits()).tos, Some(0x10));
+    }
+
+    #[test]
+    fn unset_options_stay_unset() {
+        assert_eq!(
+            SocketOptions::default().clamp(&limits()),
+            SocketOptions::default()
+        );
+    }
+}